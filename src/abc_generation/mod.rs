@@ -2,9 +2,36 @@ pub mod error;
 
 use self::error::{AbcGenerationError, ErrorType};
 use error::SourceMap;
+use notes::{dynamic_marking, round};
+use parsing::data::{Articulation, Mark, Ornament, Until};
 use sequencing::data::*;
 use trust::Trust;
 
+/// The number of beats in a bar, and the note value of each beat.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Meter {
+    beats: u32,
+    unit: u32,
+}
+
+fn articulation_decoration(articulation: Articulation) -> &'static str {
+    match articulation {
+        Articulation::Staccato => ".",
+        Articulation::Tenuto => "!tenuto!",
+        Articulation::Accent => "!>!",
+        Articulation::Marcato => "!marcato!",
+    }
+}
+
+fn ornament_decoration(ornament: Ornament) -> &'static str {
+    match ornament {
+        Ornament::Trill { .. } => "!trill!",
+        Ornament::Mordent { .. } => "!mordent!",
+        Ornament::Turn { .. } => "!turn!",
+        Ornament::Glissando { .. } | Ornament::GraceNote => "",
+    }
+}
+
 fn div_tuplet(notes_per_beat: u32) -> (u32, u32) {
     let mut tuplet = notes_per_beat;
     let mut division = 1;
@@ -43,11 +70,17 @@ pub fn generate_abc(
             writeln!(buffer, "C:{}", composer).map_err(|e| fmt_err(e, Some(source_map.clone())))?;
         }
 
-        writeln!(buffer, "M:{}/4", piece.beats)
+        let (tempo_beat_length, tempo_beat_unit) = piece.tempo_beat();
+
+        writeln!(buffer, "M:{}/{}", piece.beats, piece.beat_unit)
             .map_err(|e| fmt_err(e, Some(source_map.clone())))?;
-        writeln!(buffer, "Q:1/4={}", piece.tempo)
+        writeln!(
+            buffer,
+            "Q:{}/{}={}",
+            tempo_beat_length, tempo_beat_unit, piece.tempo
+        ).map_err(|e| fmt_err(e, Some(source_map.clone())))?;
+        writeln!(buffer, "K:{}", piece.key.to_abc())
             .map_err(|e| fmt_err(e, Some(source_map.clone())))?;
-        writeln!(buffer, "K:C").map_err(|e| fmt_err(e, Some(source_map.clone())))?;
 
         for voice in &piece.voices {
             // TODO(***realname***): Voice names with spaces only take the first word. Needs quotes?
@@ -59,17 +92,132 @@ pub fn generate_abc(
                 .map_err(|e| fmt_err(e, Some(source_map.clone())))?;
 
             if let Some(volume) = voice.volume {
-                // Using +0.5 instead of round for wasm compatibility.
-                let volume = ((volume * 127.0) + 0.5) as u8;
+                let volume = round(volume * 127.0) as u8;
                 writeln!(buffer, "%%MIDI control 7 {}", volume)
                     .map_err(|e| fmt_err(e, Some(source_map.clone())))?;
             }
 
+            for (controller, value) in voice.mix_controllers() {
+                writeln!(buffer, "%%MIDI control {} {}", controller, value)
+                    .map_err(|e| fmt_err(e, Some(source_map.clone())))?;
+            }
+
             if !voice.notes.is_empty() {
+                let mut inline_fields = voice
+                    .key_changes
+                    .iter()
+                    .map(|key_change| {
+                        (key_change.position, format!("[K:{}]", key_change.key.to_abc()))
+                    }).collect::<Vec<_>>();
+
+                for tempo_change in &voice.tempo_changes {
+                    if let Some(ramp_start) = tempo_change.ramp_start {
+                        let ramp = if tempo_change.accelerando {
+                            "accel."
+                        } else {
+                            "rit."
+                        };
+                        inline_fields.push((ramp_start, format!("\"^{}\"", ramp)));
+                    }
+
+                    let field = format!(
+                        "[Q:{}/{}={}]",
+                        tempo_beat_length, tempo_beat_unit, tempo_change.tempo
+                    );
+                    inline_fields.push((tempo_change.position, field));
+                }
+
+                let mut dynamic = DEFAULT_DYNAMIC;
+                for dynamic_change in &voice.dynamic_changes {
+                    if let Some(ramp_start) = dynamic_change.ramp_start {
+                        let hairpin = if dynamic_change.velocity < dynamic {
+                            ">"
+                        } else {
+                            "<"
+                        };
+                        inline_fields.push((ramp_start, format!("!{}(!", hairpin)));
+                        inline_fields.push((dynamic_change.position, format!("!{})!", hairpin)));
+                    }
+
+                    if let Some(marking) = dynamic_marking(dynamic_change.velocity) {
+                        inline_fields.push((dynamic_change.position, format!("!{}!", marking)));
+                    }
+                    dynamic = dynamic_change.velocity;
+                }
+
+                // The sustain pedal is marked each time it is pressed or released.
+                let mut pedal_down = false;
+                for controller_change in &voice.controller_changes {
+                    if controller_change.controller == 64
+                        && (controller_change.value >= 64) != pedal_down
+                    {
+                        pedal_down = !pedal_down;
+                        let mark = if pedal_down { "!ped!" } else { "!ped-up!" };
+                        inline_fields.push((controller_change.position, mark.to_owned()));
+                    }
+                }
+                inline_fields.sort_by_key(|&(position, _)| position);
+
+                let (notes, mut inline_fields, bar_marks) = match voice.repeat_structure {
+                    Some(ref structure) => {
+                        let (notes, inline_fields) = written_bars(
+                            &voice.notes,
+                            &inline_fields,
+                            structure,
+                            voice.divisions_per_bar,
+                        );
+
+                        (notes, inline_fields, &structure.marks[..])
+                    }
+                    None => (voice.notes.clone(), inline_fields, &[][..]),
+                };
+
+                let meter_changes = {
+                    let played_bar = |bar: u32| match voice.repeat_structure {
+                        Some(ref structure) => match structure.bars.get(bar as usize) {
+                            Some(&played_bar) => played_bar,
+                            None => structure.played_bars + bar - structure.bars.len() as u32,
+                        },
+                        None => bar,
+                    };
+
+                    let divisions_per_bar = voice.divisions_per_bar;
+                    let bars = notes
+                        .iter()
+                        .map(|note| (note.position + note.length - 1) / divisions_per_bar + 1)
+                        .max()
+                        .unwrap_or(0);
+
+                    let meter = |beats| Meter {
+                        beats: beats as u32,
+                        unit: piece.beat_unit as u32,
+                    };
+
+                    let mut meter_changes = vec![(0, meter(piece.beats))];
+                    for bar in 0..bars {
+                        let beats = piece.beats_in_bar(played_bar(bar));
+                        if meter(beats) == meter_changes.last().trust().1 {
+                            continue;
+                        }
+
+                        if bar == 0 {
+                            meter_changes.clear();
+                        }
+                        meter_changes.push((bar, meter(beats)));
+                        let field = format!("[M:{}/{}]", beats, piece.beat_unit);
+                        inline_fields.insert(0, (bar * divisions_per_bar, field));
+                    }
+                    inline_fields.sort_by_key(|&(position, _)| position);
+
+                    meter_changes
+                };
+
                 let stave_text = write_bars(
-                    &voice.notes,
-                    piece.beats as u32,
+                    &notes,
+                    &meter_changes,
                     voice.divisions_per_bar,
+                    &inline_fields,
+                    bar_marks,
                     Some(source_map),
                     Some(&voice.debug_bar_info),
                 )?;
@@ -85,24 +233,155 @@ pub fn generate_abc(
     Ok(buffer)
 }
 
+/// Undo the expansion of repeats, so that each bar is written once, as it was in the source.
+fn written_bars(
+    notes: &[Note],
+    inline_fields: &[(u32, String)],
+    structure: &RepeatStructure,
+    divisions_per_bar: u32,
+) -> (Vec<Note>, Vec<(u32, String)>) {
+    let written_position = |position: u32| {
+        let played_bar = position / divisions_per_bar;
+        let offset = position % divisions_per_bar;
+
+        if played_bar >= structure.played_bars {
+            let bar = structure.bars.len() as u32 + played_bar - structure.played_bars;
+            Some(bar * divisions_per_bar + offset)
+        } else {
+            structure
+                .bars
+                .iter()
+                .position(|&bar| bar == played_bar)
+                .map(|bar| bar as u32 * divisions_per_bar + offset)
+        }
+    };
+
+    let mut written_notes = Vec::new();
+    for (bar, &played_bar) in structure.bars.iter().enumerate() {
+        let bar_start = bar as u32 * divisions_per_bar;
+        let played_start = played_bar * divisions_per_bar;
+
+        written_notes.extend(
+            notes
+                .iter()
+                .filter(|note| note.position / divisions_per_bar == played_bar)
+                .map(|note| Note {
+                    position: note.position - played_start + bar_start,
+                    ..*note
+                }),
+        );
+    }
+
+    let mut written_fields: Vec<(u32, String)> = Vec::new();
+    for &(position, ref field) in inline_fields {
+        if let Some(position) = written_position(position) {
+            let duplicate = written_fields
+                .iter()
+                .any(|&(other_position, ref other_field)| {
+                    other_position == position && other_field == field
+                });
+
+            if !duplicate {
+                written_fields.push((position, field.clone()));
+            }
+        }
+    }
+    written_fields.sort_by_key(|&(position, _)| position);
+
+    (written_notes, written_fields)
+}
+
+/// The barline written before the given bar, along with the decorations which end the previous
+/// bar, and the volta and decorations which begin the given bar.
+fn barline(bar_marks: &[(u32, Mark)], bar: u32) -> (String, &'static str, String) {
+    let mut start_repeat = false;
+    let mut end_repeat = false;
+    let mut before = String::new();
+    let mut after = String::new();
+
+    fn until_suffix(until: Until) -> &'static str {
+        match until {
+            Until::End => "",
+            Until::Fine => "alfine",
+            Until::Coda => "alcoda",
+        }
+    }
+
+    // The first of two codas marks where to jump from, and so ends its bar.
+    let codas = bar_marks
+        .iter()
+        .filter(|&&(_, mark)| mark == Mark::Coda)
+        .collect::<Vec<_>>();
+    let to_coda = if codas.len() > 1 { codas.first().cloned() } else { None };
+
+    for bar_mark in bar_marks.iter().filter(|&&(mark_bar, _)| mark_bar == bar) {
+        let mark = bar_mark.1;
+        match mark {
+            Mark::Coda if Some(bar_mark) == to_coda => before.push_str("!coda!"),
+            Mark::StartRepeat => start_repeat = true,
+            Mark::EndRepeat { .. } => end_repeat = true,
+            Mark::Volta { number } => after.insert_str(0, &format!("[{}", number)),
+            Mark::Segno => after.push_str("!segno!"),
+            Mark::Coda => after.push_str("!coda!"),
+            Mark::Fine => before.push_str("!fine!"),
+            Mark::DaCapo { until } => before.push_str(&format!("!D.C.{}!", until_suffix(until))),
+            Mark::DalSegno { until } => {
+                before.push_str(&format!("!D.S.{}!", until_suffix(until)))
+            }
+        }
+    }
+
+    let symbol = match (end_repeat, start_repeat) {
+        (true, true) => ":|:",
+        (true, false) => ":|",
+        (false, true) => "|:",
+        (false, false) => "|",
+    };
+
+    (before, symbol, after)
+}
+
+/// Write notes as ABC bars. The meter is given for each bar at which it changes, starting with the
+/// first bar.
 fn write_bars(
     stave_notes: &[Note],
-    beats_per_bar: u32,
+    meter_changes: &[(u32, Meter)],
     divisions_per_bar: u32,
+    inline_fields: &[(u32, String)],
+    bar_marks: &[(u32, Mark)],
     source_map: Option<&SourceMap>,
     debug_bar_info: Option<&[DebugBarInfo]>,
 ) -> Result<String, AbcGenerationError> {
     use self::error::fmt_err;
-    use notes::lcm;
+    use notes::{gcd, lcm};
     use std::fmt::Write;
 
     let mut buffer = String::new();
 
-    let max_divisions = divisions_per_bar;
-    let notes_per_bar = lcm(max_divisions, beats_per_bar);
-    let notes_per_beat = notes_per_bar / beats_per_bar;
+    let beats_in_bar = |bar: u32| {
+        meter_changes
+            .iter()
+            .take_while(|&&(change_bar, _)| change_bar <= bar)
+            .last()
+            .map(|&(_, meter)| meter.beats)
+            .trust()
+    };
+
+    // Every beat is split into the same number of notes, which must be enough for each note to
+    // start and end on a whole number of them.
+    let notes_per_beat = stave_notes
+        .iter()
+        .flat_map(|note| vec![note.position, note.position + note.length])
+        .fold(1, |notes_per_beat, position| {
+            let beats = beats_in_bar(position / divisions_per_bar);
+            let offset_in_beats = (position % divisions_per_bar) * beats;
+            lcm(notes_per_beat, divisions_per_bar / gcd(divisions_per_bar, offset_in_beats))
+        });
     let (beat_division, tuplet) = div_tuplet(notes_per_beat);
 
+    // `div_tuplet` divides quarter notes, which have to be scaled to the beat unit of the meter.
+    let beat_division = beat_division * meter_changes[0].1.unit / 4;
+
     if tuplet > 9 {
         let inciting_bar = debug_bar_info.and_then(|debug_bar_info| {
             debug_bar_info
@@ -124,29 +403,63 @@ fn write_bars(
 
     writeln!(buffer, "L:1/{}", beat_division).map_err(|e| fmt_err(e, source_map.cloned()))?;
 
-    let scale = notes_per_bar as u32 / divisions_per_bar;
-
-    let mut notes = stave_notes.iter();
-    let mut cursor = 0;
-    let mut abc_notes = vec![];
-
-    let end_position = {
+    let latest_bar = {
         let latest_end = stave_notes
             .iter()
             .map(|note| note.position + note.length)
             .max()
             .trust();
-        let latest_bar = (latest_end + divisions_per_bar - 1) / divisions_per_bar;
-        latest_bar * divisions_per_bar * scale
+        (latest_end + divisions_per_bar - 1) / divisions_per_bar
     };
 
-    loop {
-        let next_barline = ((cursor / notes_per_bar) + 1) * notes_per_bar;
+    // The position in written notes at which each bar starts, along with the end of the last bar.
+    let bar_starts = {
+        let mut bar_starts = vec![0];
+        for bar in 0..latest_bar {
+            let bar_start = bar_starts[bar as usize];
+            bar_starts.push(bar_start + notes_per_beat * beats_in_bar(bar));
+        }
+        bar_starts
+    };
+
+    let written = |position: u32| {
+        let bar = position / divisions_per_bar;
+        let offset = position % divisions_per_bar;
+
+        match bar_starts.get(bar as usize) {
+            Some(&bar_start) => {
+                bar_start + offset * notes_per_beat * beats_in_bar(bar) / divisions_per_bar
+            }
+            None => u32::MAX,
+        }
+    };
+
+    let bar_at = |written_position: u32| {
+        bar_starts
+            .iter()
+            .rposition(|&bar_start| bar_start <= written_position)
+            .trust() as u32
+    };
+
+    let next_barline = |cursor: u32| {
+        bar_starts
+            .iter()
+            .cloned()
+            .find(|&bar_start| bar_start > cursor)
+            .unwrap_or(u32::MAX)
+    };
+
+    let mut notes = stave_notes.iter();
+    let mut cursor = 0;
+    let mut abc_notes = vec![];
+
+    let end_position = bar_starts[latest_bar as usize];
 
+    loop {
         let note = notes.next();
         let position = note
-            .map(|note| note.position * scale)
-            .unwrap_or_else(|| ::std::cmp::min(next_barline, end_position));
+            .map(|note| written(note.position))
+            .unwrap_or_else(|| ::std::cmp::min(next_barline(cursor), end_position));
 
         if position < cursor {
             continue;
@@ -164,24 +477,17 @@ fn write_bars(
 
         if rest_length > 0 {
             if tuplet == 1 {
-                let mut rests_remaining = rest_length;
-                let rests_until_barline = ::std::cmp::min(rests_remaining, next_barline - cursor);
+                let mut rest_cursor = cursor;
+
+                while rest_cursor < position {
+                    let rests_until_barline =
+                        ::std::cmp::min(position, next_barline(rest_cursor)) - rest_cursor;
 
-                if rests_until_barline > 0 {
                     abc_notes.push((
                         note_string("z", rests_until_barline, ""),
                         rests_until_barline,
                     ));
-                    rests_remaining -= rests_until_barline;
-                }
-
-                while rests_remaining >= notes_per_bar {
-                    abc_notes.push((note_string("z", notes_per_bar, ""), notes_per_bar));
-                    rests_remaining -= notes_per_bar;
-                }
-
-                if rests_remaining > 0 {
-                    abc_notes.push((note_string("z", rests_remaining, ""), rests_remaining));
+                    rest_cursor += rests_until_barline;
                 }
             } else {
                 let rest_string = "z";
@@ -195,13 +501,17 @@ fn write_bars(
 
         match note {
             Some(note) => {
-                let chord: Vec<Note> = stave_notes
+                let (grace_notes, chord): (Vec<Note>, Vec<Note>) = stave_notes
                     .iter()
                     .filter(|other| other.position == note.position)
                     .cloned()
-                    .collect();
+                    .partition(|other| other.ornament == Some(Ornament::GraceNote));
 
-                let min_chord_length = chord.iter().map(|note| note.length * scale).min().trust();
+                let min_chord_length = chord
+                    .iter()
+                    .map(|note| written(note.position + note.length) - written(note.position))
+                    .min()
+                    .trust();
 
                 fn chord_string(chord: &[Note]) -> String {
                     let chord_notes_string = chord
@@ -218,35 +528,21 @@ fn write_bars(
                 }
 
                 let chord_string = chord_string(&chord);
+                let first_chord_note = abc_notes.len();
 
                 if tuplet == 1 {
-                    let next_barline = ((cursor / notes_per_bar) + 1) * notes_per_bar;
+                    let chord_end = cursor + min_chord_length;
+                    let mut chord_cursor = cursor;
 
-                    let mut length_remaining = min_chord_length;
-                    let length_until_barline =
-                        ::std::cmp::min(length_remaining, next_barline - cursor);
+                    while chord_cursor < chord_end {
+                        let length_until_barline =
+                            ::std::cmp::min(chord_end, next_barline(chord_cursor)) - chord_cursor;
 
-                    if length_until_barline > 0 {
                         abc_notes.push((
                             note_string(&chord_string, length_until_barline, "-"),
                             length_until_barline,
                         ));
-                        length_remaining -= length_until_barline;
-                    }
-
-                    while length_remaining >= notes_per_bar {
-                        abc_notes.push((
-                            note_string(&chord_string, notes_per_bar, "-"),
-                            notes_per_bar,
-                        ));
-                        length_remaining -= notes_per_bar;
-                    }
-
-                    if length_remaining > 0 {
-                        abc_notes.push((
-                            note_string(&chord_string, length_remaining, "-"),
-                            length_remaining,
-                        ));
+                        chord_cursor += length_until_barline;
                     }
 
                     if let Some(tied_note) = abc_notes.last_mut() {
@@ -260,6 +556,30 @@ fn write_bars(
                     abc_notes.push((chord_string, 1));
                 }
 
+                // Only the first of a series of tied notes is given the articulation.
+                let articulation = chord.iter().filter_map(|note| note.articulation).next();
+                if let Some(articulation) = articulation {
+                    let decoration = articulation_decoration(articulation);
+                    abc_notes[first_chord_note].0.insert_str(0, decoration);
+                }
+
+                let ornament = chord.iter().filter_map(|note| note.ornament).next();
+                if let Some(ornament) = ornament {
+                    let decoration = ornament_decoration(ornament);
+                    abc_notes[first_chord_note].0.insert_str(0, decoration);
+                }
+
+                // Grace notes are written before any decorations.
+                if !grace_notes.is_empty() {
+                    let grace_string = grace_notes
+                        .iter()
+                        .map(|note| note.midi.to_abc())
+                        .collect::<Vec<&str>>()
+                        .join("");
+                    let grace_string = format!("{{{}}}", grace_string);
+                    abc_notes[first_chord_note].0.insert_str(0, &grace_string);
+                }
+
                 cursor += min_chord_length;
             }
             None => break,
@@ -269,35 +589,66 @@ fn write_bars(
     assert!(cursor == end_position);
     assert!(abc_notes.len() % tuplet as usize == 0);
 
-    let mut written_notes = 0;
+    let mut written_bar = 0;
+    let mut written_position = 0;
+    let mut inline_fields = inline_fields.iter().peekable();
+
+    let mut write_inline_fields = |buffer: &mut String, written_position: u32| {
+        while let Some(&&(position, ref field)) = inline_fields.peek() {
+            if written(position) > written_position {
+                break;
+            }
+
+            write!(buffer, "{}", field).map_err(|e| fmt_err(e, source_map.cloned()))?;
+            inline_fields.next();
+        }
+
+        Ok(())
+    };
+
+    let write_barline = |buffer: &mut String, bar: u32| {
+        let (before, symbol, after) = barline(bar_marks, bar);
+        write!(buffer, "{}{}\n{}", before, symbol, after)
+            .map_err(|e| fmt_err(e, source_map.cloned()))
+    };
+
+    let (_, first_symbol, first_after) = barline(bar_marks, 0);
+    if first_symbol != "|" {
+        write!(buffer, "{}", first_symbol).map_err(|e| fmt_err(e, source_map.cloned()))?;
+    }
+    write!(buffer, "{}", first_after).map_err(|e| fmt_err(e, source_map.cloned()))?;
 
     match tuplet {
         1 => for (note, length) in abc_notes {
-            if written_notes >= notes_per_bar {
-                written_notes -= notes_per_bar;
-                assert!(written_notes < notes_per_bar);
-                writeln!(buffer, "|").map_err(|e| fmt_err(e, source_map.cloned()))?;
+            if written_position >= bar_starts[written_bar as usize + 1] {
+                written_bar += 1;
+                assert!(written_position < bar_starts[written_bar as usize + 1]);
+                write_barline(&mut buffer, written_bar)?;
             }
 
+            write_inline_fields(&mut buffer, written_position)?;
             write!(buffer, "{}", note).map_err(|e| fmt_err(e, source_map.cloned()))?;
-            written_notes += length;
+            written_position += length;
         },
         n => for chunk in abc_notes.chunks(n as usize) {
-            if written_notes >= notes_per_bar {
-                written_notes -= notes_per_bar;
-                assert!(written_notes < notes_per_bar);
-                writeln!(buffer, "|").map_err(|e| fmt_err(e, source_map.cloned()))?;
+            if written_position >= bar_starts[written_bar as usize + 1] {
+                written_bar += 1;
+                assert!(written_position < bar_starts[written_bar as usize + 1]);
+                write_barline(&mut buffer, written_bar)?;
             }
 
+            write_inline_fields(&mut buffer, written_position)?;
             write!(buffer, "({}", n).map_err(|e| fmt_err(e, source_map.cloned()))?;
             for &(ref note, length) in chunk {
                 write!(buffer, "{}", note).map_err(|e| fmt_err(e, source_map.cloned()))?;
-                written_notes += length;
+                written_position += length;
             }
         },
     }
 
-    writeln!(buffer, "|").map_err(|e| fmt_err(e, source_map.cloned()))?;
+    let (last_before, last_symbol, _) = barline(bar_marks, bar_at(written_position));
+    writeln!(buffer, "{}{}", last_before, last_symbol)
+        .map_err(|e| fmt_err(e, source_map.cloned()))?;
 
     Ok(buffer)
 }
@@ -320,8 +671,10 @@ mod tests {
         assert_eq!(
             write_bars(
                 &voice.notes,
-                notes_per_bar,
+                &[(0, Meter { beats: notes_per_bar, unit: 4 })],
                 voice.divisions_per_bar,
+                &[],
+                &[],
                 None,
                 None
             ).unwrap(),
@@ -343,8 +696,10 @@ mod tests {
         assert!(
             write_bars(
                 &voice.notes,
-                notes_per_bar,
+                &[(0, Meter { beats: notes_per_bar, unit: 4 })],
                 voice.divisions_per_bar,
+                &[],
+                &[],
                 None,
                 None
             ).is_err()
@@ -520,6 +875,16 @@ mod tests {
         write_bars_test(source, "L:1/16\n=C3=D=E3=F=G3=F=E3=D|\n=C16|\n", 4);
     }
 
+    #[test]
+    fn ornaments_as_decorations() {
+        let source = "voice A {} play A { :| {d}c c~tr {Bd}c~mor* C~turn }";
+        write_bars_test(
+            source,
+            "L:1/4\n{=d}=c!trill!=c{=B,=d}!mordent!.=c!turn!=C|\n",
+            4,
+        );
+    }
+
     #[test]
     fn note_tied_across_bar() {
         let source = "voice A {} play A { :| CEG. | ..EC }";
@@ -549,4 +914,76 @@ mod tests {
         let source = "voice A {} play A { :| C - | - C }";
         write_bars_test(source, "L:1/4\n=C2z2|\nz2=C2|\n", 4);
     }
+
+    #[test]
+    fn test_repeat_barlines() {
+        let marks = [
+            (0, Mark::StartRepeat),
+            (1, Mark::Volta { number: 1 }),
+            (2, Mark::EndRepeat { count: 2 }),
+            (2, Mark::Volta { number: 2 }),
+            (3, Mark::EndRepeat { count: 2 }),
+            (3, Mark::StartRepeat),
+        ];
+
+        let empty = String::new();
+        assert_eq!(barline(&marks, 0), (empty.clone(), "|:", empty.clone()));
+        assert_eq!(barline(&marks, 1), (empty.clone(), "|", "[1".to_owned()));
+        assert_eq!(barline(&marks, 2), (empty.clone(), ":|", "[2".to_owned()));
+        assert_eq!(barline(&marks, 3), (empty.clone(), ":|:", empty.clone()));
+        assert_eq!(barline(&marks, 4), (empty.clone(), "|", empty.clone()));
+    }
+
+    #[test]
+    fn test_navigation_decorations() {
+        let marks = [
+            (0, Mark::Segno),
+            (1, Mark::Fine),
+            (1, Mark::Coda),
+            (2, Mark::DalSegno { until: Until::Fine }),
+            (2, Mark::Coda),
+        ];
+
+        let empty = String::new();
+        assert_eq!(barline(&marks, 0), (empty.clone(), "|", "!segno!".to_owned()));
+        assert_eq!(
+            barline(&marks, 1),
+            ("!fine!!coda!".to_owned(), "|", empty.clone())
+        );
+        assert_eq!(
+            barline(&marks, 2),
+            ("!D.S.alfine!".to_owned(), "|", "!coda!".to_owned())
+        );
+    }
+
+    #[test]
+    fn meter_changes_between_bars() {
+        use lexing;
+        use parsing;
+        use sequencing;
+
+        let source = "voice A {} play A { :| C D E F | C D E | C D E F }";
+        let (tokens, source_map) = lexing::lex(source, None).expect("ERROR IN LEXER");
+        let parse_tree = parsing::parse(&tokens, &source_map).expect("ERROR IN PARSER");
+        let pieces =
+            sequencing::sequence_pieces(&parse_tree, &source_map).expect("ERROR IN SEQUENCER");
+        let voice = &pieces[0].voices[0];
+
+        assert_eq!(
+            write_bars(
+                &voice.notes,
+                &[
+                    (0, Meter { beats: 4, unit: 4 }),
+                    (1, Meter { beats: 3, unit: 4 }),
+                    (2, Meter { beats: 4, unit: 4 }),
+                ],
+                voice.divisions_per_bar,
+                &[],
+                &[],
+                None,
+                None
+            ).unwrap(),
+            "L:1/4\n=C=D=E=F|\n=C=D=E|\n=C=D=E=F|\n"
+        );
+    }
 }
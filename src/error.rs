@@ -131,7 +131,37 @@ pub fn fmt_error(
     col: usize,
     width: usize,
 ) -> Result<(), Error> {
-    use self::colors::{BLUE, RED, WHITE};
+    use self::colors::{RED, WHITE};
+
+    writeln!(f, "{}: {}", RED.paint("error"), WHITE.paint(message))?;
+    fmt_location(f, RED, filename, context, line, col, width)
+}
+
+pub fn fmt_note(f: &mut Formatter, message: &str, loc: &SourceLoc) -> Result<(), Error> {
+    use self::colors::{CYAN, WHITE};
+
+    writeln!(f, "{}: {}", CYAN.paint("note"), WHITE.paint(message))?;
+    fmt_location(
+        f,
+        CYAN,
+        loc.info.filename(),
+        loc.cause_line(),
+        loc.line,
+        loc.col,
+        loc.width,
+    )
+}
+
+fn fmt_location(
+    f: &mut Formatter,
+    color: ::ansi_term::Style,
+    filename: &str,
+    context: &str,
+    line: usize,
+    col: usize,
+    width: usize,
+) -> Result<(), Error> {
+    use self::colors::BLUE;
 
     let line_prefix = format!("{} |    ", line);
     let underline = format!(
@@ -143,16 +173,14 @@ pub fn fmt_error(
 
     writeln!(
         f,
-        "{}: {}\n   {}: {}:{}:{}\n\n{}{}\n{}",
-        RED.paint("error"),
-        WHITE.paint(message),
+        "   {}: {}:{}:{}\n\n{}{}\n{}",
         BLUE.paint("in"),
         filename,
         line,
         col,
         BLUE.paint(line_prefix),
         context,
-        RED.paint(underline)
+        color.paint(underline)
     )
 }
 
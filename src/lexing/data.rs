@@ -20,6 +20,7 @@ pub enum Token<'a> {
     LeftBrace,
     RightBrace,
     Comma,
+    Slash,
     BlankLine,
     Num(i64),
     Key(&'a str),
@@ -27,6 +28,22 @@ pub enum Token<'a> {
     Str(&'a str),
 
     Barline,
+    StartRepeat,
+    EndRepeat(i64),
+    Volta(i64),
+    StartTuplet(i64),
+    EndTuplet,
+    StartChord(&'a str),
+    EndChord(&'a str),
+    StartGrace,
+    EndGrace,
+    ChordName(&'a str),
+    Navigation(&'a str),
+    Ramp(&'a str),
+    Dynamic(&'a str),
+    Bend(&'a str),
+    Pedal(&'a str),
+    Glissando,
     Rest,
     Hit,
     Ditto,
@@ -51,12 +68,29 @@ impl<'a> Token<'a> {
             LeftBrace => "'{'",
             RightBrace => "'}'",
             Comma => "','",
+            Slash => "'/'",
             BlankLine => "<blank_line>",
             Num(_) => "<number>",
             Key(_) => "<key>:",
             Ident(_) => "<identifier>",
             Str(_) => "<string>",
             Barline => "'|'",
+            StartRepeat => "'|:'",
+            EndRepeat(_) => "':|'",
+            Volta(_) => "'[<number>'",
+            StartTuplet(_) => "'(<number>'",
+            EndTuplet => "')'",
+            StartChord(_) => "'['",
+            EndChord(_) => "']'",
+            StartGrace => "'{'",
+            EndGrace => "'}'",
+            ChordName(_) => "<chord_symbol>",
+            Navigation(_) => "<navigation_mark>",
+            Ramp(_) => "<gradual_change>",
+            Dynamic(_) => "<dynamic>",
+            Bend(_) => "<bend>",
+            Pedal(_) => "<pedal_mark>",
+            Glissando => "'/'",
             Rest => "'-'",
             Hit => "'x'",
             Ditto => "'\"'",
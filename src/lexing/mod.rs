@@ -5,6 +5,7 @@ use self::data::*;
 use self::error::{ErrorType, LexingError};
 
 use error::{SourceInfo, SourceLoc, SourceMap};
+use notes::{controller_number, pressure_note};
 use regex::Regex;
 use trust::Trust;
 
@@ -21,35 +22,71 @@ fn line_col_at(source: &str, position: usize) -> (usize, usize) {
     (1, source.len())
 }
 
+const NAVIGATION_PATTERN: &str = "(?P<navigation>segno|coda|fine|D\\.[CS]\\.(\\s+al\\s+(fine|coda))?)";
+
+const MUSIC_PATTERN: &str = "\
+    (?P<ramp>rit\\.|accel\\.)|\
+    (?P<note>[a-gA-G][=_\\#]*[,']*(~(tr|mor|turn))?[!^~*]?)|\
+    (?P<part>\\*[a-zA-Z_][a-zA-Z0-9_]*([+\\-]\\d+|<|\\*\\d+)*)|\
+    (?P<repeat>:\\|:|\\|:|:\\|(x\\d+)?)|\
+    (?P<volta>\\[\\d+)|\
+    (?P<tuplet>\\(\\d+|\\))|\
+    (?P<chord>[v^]?\\[|\\][!^~*]?)|\
+    (?P<grace>\\{|\\})|\
+    (?P<symbol>[\\.\\-x\"%])|\
+    (?P<number>\\d+)|\
+    (?P<barline>\\|)|\
+    (?P<comment>//[^\n]*)|\
+    (?P<glissando>/)|\
+    (?P<whitespace>([\t ]|;)+)|\
+    (?P<error>.)\
+    ";
+
 lazy_static! {
     static ref STRUCTURE_REGEX: Regex = Regex::new(
         "\
          (?P<keyword>part|piece|play|section|voice)|\
          (?P<key>([a-zA-Z_][a-zA-Z0-9_#,'=\\-]*\\s*|:)?:)|\
-         (?P<ident>[a-zA-Z_][a-zA-Z0-9_ ]*)|\
+         (?P<ident>[a-zA-Z_][a-zA-Z0-9_# ]*)|\
          (?P<string>\"((\\\\\")|[^\"])*\")|\
          (?P<number>[+\\-]?\\d+)|\
          (?P<delim>[{},])|\
-         (?P<staveline>\\|([^;}\n]*))|\
+         (?P<staveline>\\|((\\{[^{}\n]*\\}|[^;{}\n])*))|\
          (?P<comment>//[^\n]*)|\
+         (?P<slash>/)|\
          (?P<blank>\n\\s*\n)|\
          (?P<newline>\n)|\
          (?P<whitespace>([\t ]|;)+)|\
          (?P<error>.)\
          "
     ).trust();
-    static ref MUSIC_REGEX: Regex = Regex::new(
-        "\
-         (?P<note>[a-gA-G][=_\\#]*[,']*)|\
-         (?P<part>\\*[a-zA-Z_][a-zA-Z0-9_]*)|\
-         (?P<symbol>[\\.\\-x\"%])|\
-         (?P<number>\\d+)|\
-         (?P<barline>\\|)|\
-         (?P<comment>//[^\n]*)|\
-         (?P<whitespace>([\t ]|;)+)|\
-         (?P<error>.)\
-         "
-    ).trust();
+    static ref MUSIC_REGEX: Regex =
+        Regex::new(&format!("{}|{}", NAVIGATION_PATTERN, MUSIC_PATTERN)).trust();
+
+    // Dynamic markings like `f` would otherwise be read as notes, so they are only found in the
+    // staves where they belong.
+    static ref DYNAMICS_REGEX: Regex = Regex::new(&format!(
+        "(?P<dynamic>p{{1,3}}|f{{1,3}}|m[pf]|[<>])|{}|{}",
+        NAVIGATION_PATTERN, MUSIC_PATTERN
+    )).trust();
+
+    // Chord symbols like `Cmaj7` or `G7/B` are read in place of notes in `chords:` staves.
+    static ref CHORDS_REGEX: Regex = Regex::new(&format!(
+        "{}|(?P<chord_symbol>[v^]?[A-G][#b_]?[a-zA-Z0-9#+]*(/[A-G][#b_]?)?)|{}",
+        NAVIGATION_PATTERN, MUSIC_PATTERN
+    )).trust();
+
+    // Pedal marks press (`v`) and release (`^`) the sustain pedal.
+    static ref PEDAL_REGEX: Regex = Regex::new(&format!(
+        "{}|(?P<pedal>[v^])|{}",
+        NAVIGATION_PATTERN, MUSIC_PATTERN
+    )).trust();
+
+    // Bends are measured in semitones, which may be fractional.
+    static ref BEND_REGEX: Regex = Regex::new(&format!(
+        "{}|(?P<bend>[+\\-]?\\d+(\\.\\d+)?)|{}",
+        NAVIGATION_PATTERN, MUSIC_PATTERN
+    )).trust();
 }
 
 #[derive(Debug)]
@@ -77,6 +114,7 @@ pub fn lex<'a>(
         "number",
         "delim",
         "staveline",
+        "slash",
         "blank",
         "newline",
         "whitespace",
@@ -85,8 +123,20 @@ pub fn lex<'a>(
     ];
 
     const STAVE_CAPTURE_PRIORITIES: &[&str] = &[
+        "dynamic",
+        "navigation",
+        "bend",
+        "pedal",
+        "ramp",
+        "chord_symbol",
         "note",
         "part",
+        "repeat",
+        "volta",
+        "tuplet",
+        "chord",
+        "grace",
+        "glissando",
         "barline",
         "symbol",
         "number",
@@ -171,11 +221,46 @@ pub fn lex<'a>(
 
                 context = Context::Normal;
             }
+            "slash" => tokens.push(MetaToken {
+                token: Slash,
+                span,
+                loc,
+            }),
             "staveline" => {
                 context = Context::InStave;
                 let start = span.0;
+                let gradual_slashes = match tokens.last() {
+                    Some(&MetaToken {
+                        token: Key(prefix), ..
+                    }) => {
+                        prefix == "bend"
+                            || prefix == "pressure"
+                            || controller_number(prefix).is_some()
+                            || pressure_note(prefix).is_some()
+                    }
+                    _ => false,
+                };
+
+                let music_regex = match tokens.last() {
+                    Some(&MetaToken {
+                        token: Key("dyn"), ..
+                    }) => &*DYNAMICS_REGEX,
+                    Some(&MetaToken {
+                        token: Key("chords"),
+                        ..
+                    }) => &*CHORDS_REGEX,
+                    Some(&MetaToken {
+                        token: Key("bend"), ..
+                    }) => &*BEND_REGEX,
+                    Some(&MetaToken {
+                        token: Key("ped"), ..
+                    }) => &*PEDAL_REGEX,
+                    _ => &*MUSIC_REGEX,
+                };
+
+                let stave_text = text;
 
-                for capture in MUSIC_REGEX.captures_iter(text) {
+                for capture in music_regex.captures_iter(text) {
                     let mut group = None;
 
                     for group_name in STAVE_CAPTURE_PRIORITIES {
@@ -202,11 +287,142 @@ pub fn lex<'a>(
                             span,
                             loc,
                         }),
+                        "chord_symbol" => tokens.push(MetaToken {
+                            token: ChordName(text),
+                            span,
+                            loc,
+                        }),
                         "part" => tokens.push(MetaToken {
                             token: PlayPart(&text[1..]),
                             span,
                             loc,
                         }),
+                        "repeat" => {
+                            if text.starts_with(':') {
+                                let count = match text.find('x') {
+                                    Some(x) => text[(x + 1)..].parse::<i64>().trust(),
+                                    None => 2,
+                                };
+                                tokens.push(MetaToken {
+                                    token: EndRepeat(count),
+                                    span,
+                                    loc: loc.clone(),
+                                });
+                            }
+
+                            if text.ends_with(':') {
+                                tokens.push(MetaToken {
+                                    token: StartRepeat,
+                                    span,
+                                    loc,
+                                });
+                            }
+                        }
+                        "navigation" => {
+                            // `D.C.` is also the notes D and C, each extended, so it is only read
+                            // as a jump when it ends a bar, as jumps do.
+                            let after = stave_text[m.end()..].trim_start();
+                            let ends_bar = after.is_empty()
+                                || after.starts_with(&['|', ':'][..])
+                                || after.starts_with("//");
+
+                            if ends_bar || !text.starts_with("D.C.") {
+                                tokens.push(MetaToken {
+                                    token: Navigation(text),
+                                    span,
+                                    loc,
+                                });
+                                continue;
+                            }
+
+                            let notes = [Note("D"), ExtendNote, Note("C"), ExtendNote];
+                            for (offset, &token) in notes.iter().enumerate() {
+                                let (line, col) = line_col_at(source, start + offset);
+                                tokens.push(MetaToken {
+                                    token,
+                                    span: Span(start + offset, &text[offset..(offset + 1)]),
+                                    loc: SourceLoc {
+                                        line,
+                                        col,
+                                        width: 1,
+                                        ..loc.clone()
+                                    },
+                                });
+                            }
+
+                            // As notes, `al` is a note followed by an unexpected `l`.
+                            if let Some(al) = text.find("al") {
+                                let (line, col) = line_col_at(source, start + al + 1);
+                                return Err(LexingError {
+                                    loc: SourceLoc {
+                                        line,
+                                        col,
+                                        width: 1,
+                                        ..loc
+                                    },
+                                    error: ErrorType::UnexpectedCharacter {
+                                        text: "l".to_owned(),
+                                        context: "stave",
+                                    },
+                                });
+                            }
+                        }
+                        "ramp" => tokens.push(MetaToken {
+                            token: Ramp(text),
+                            span,
+                            loc,
+                        }),
+                        "dynamic" => {
+                            // Hairpins change gradually, like a `rit.` or `accel.`.
+                            let token = match text {
+                                "<" | ">" => Ramp(text),
+                                _ => Dynamic(text),
+                            };
+                            tokens.push(MetaToken { token, span, loc });
+                        }
+                        "bend" => tokens.push(MetaToken {
+                            token: Bend(text),
+                            span,
+                            loc,
+                        }),
+                        "pedal" => tokens.push(MetaToken {
+                            token: Pedal(text),
+                            span,
+                            loc,
+                        }),
+                        "glissando" => {
+                            // In value staves such as `bend:`, `/` changes gradually towards the
+                            // next value.
+                            let token = if gradual_slashes { Ramp(text) } else { Glissando };
+                            tokens.push(MetaToken { token, span, loc });
+                        }
+                        "volta" => tokens.push(MetaToken {
+                            token: Volta(text[1..].parse::<i64>().trust()),
+                            span,
+                            loc,
+                        }),
+                        "tuplet" => {
+                            let token = match text {
+                                ")" => EndTuplet,
+                                _ => StartTuplet(text[1..].parse::<i64>().trust()),
+                            };
+                            tokens.push(MetaToken { token, span, loc });
+                        }
+                        "chord" => {
+                            let token = if text.starts_with(']') {
+                                EndChord(text)
+                            } else {
+                                StartChord(text)
+                            };
+                            tokens.push(MetaToken { token, span, loc });
+                        }
+                        "grace" => {
+                            let token = match text {
+                                "{" => StartGrace,
+                                _ => EndGrace,
+                            };
+                            tokens.push(MetaToken { token, span, loc });
+                        }
                         "barline" => tokens.push(MetaToken {
                             token: Barline,
                             span,
@@ -351,9 +567,9 @@ mod tests {
     #[test]
     fn invalid_tokens_in_stave() {
         assert_eq!(
-            lex("   :|{}|", None).unwrap_err().error,
+            lex("   :|@|", None).unwrap_err().error,
             ErrorType::UnexpectedCharacter {
-                text: "{".to_owned(),
+                text: "@".to_owned(),
                 context: "stave",
             },
         );
@@ -474,6 +690,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn lex_key_signature() {
+        lextest(
+            "key: F# minor",
+            vec![Key("key"), Ident("F# minor")],
+        );
+    }
+
     #[test]
     fn lex_multiple_fields() {
         lextest(
@@ -505,6 +729,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn lex_meter() {
+        lextest(
+            "meter: 6/8 // Compound",
+            vec![Key("meter"), Num(6), Slash, Num(8)],
+        );
+    }
+
     #[test]
     fn lex_blank_lines() {
         lextest(
@@ -595,4 +827,394 @@ mod tests {
             vec![LeftBrace, Key("A"), Barline, Hit, RightBrace],
         );
     }
+
+    #[test]
+    fn lex_play_part_with_modifiers() {
+        lextest(
+            ":| *Theme+5 *Theme-12<*2 *Theme",
+            vec![
+                Key(""),
+                Barline,
+                PlayPart("Theme+5"),
+                PlayPart("Theme-12<*2"),
+                PlayPart("Theme"),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_repeats() {
+        lextest(
+            ":|: C :|x4 D :|: E :|",
+            vec![
+                Key(""),
+                StartRepeat,
+                Note("C"),
+                EndRepeat(4),
+                Note("D"),
+                EndRepeat(2),
+                StartRepeat,
+                Note("E"),
+                EndRepeat(2),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_voltas() {
+        lextest(
+            ":|: C [1 D :| [2 E |",
+            vec![
+                Key(""),
+                StartRepeat,
+                Note("C"),
+                Volta(1),
+                Note("D"),
+                EndRepeat(2),
+                Volta(2),
+                Note("E"),
+                Barline,
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_navigation_marks() {
+        lextest(
+            ":| segno C | coda D fine | E D.S. al coda | coda F | D.C. | D.C.  al fine",
+            vec![
+                Key(""),
+                Barline,
+                Navigation("segno"),
+                Note("C"),
+                Barline,
+                Navigation("coda"),
+                Note("D"),
+                Navigation("fine"),
+                Barline,
+                Note("E"),
+                Navigation("D.S. al coda"),
+                Barline,
+                Navigation("coda"),
+                Note("F"),
+                Barline,
+                Navigation("D.C."),
+                Barline,
+                Navigation("D.C.  al fine"),
+            ],
+        );
+    }
+
+    #[test]
+    fn notes_are_not_navigation_marks() {
+        lextest(
+            ":| D. C. c",
+            vec![Key(""), Barline, Note("D"), ExtendNote, Note("C"), ExtendNote, Note("c")],
+        );
+        lextest(
+            ":| D.C. E F | G D.C. c |",
+            vec![
+                Key(""),
+                Barline,
+                Note("D"),
+                ExtendNote,
+                Note("C"),
+                ExtendNote,
+                Note("E"),
+                Note("F"),
+                Barline,
+                Note("G"),
+                Note("D"),
+                ExtendNote,
+                Note("C"),
+                ExtendNote,
+                Note("c"),
+                Barline,
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_ramps() {
+        lextest(
+            "tempo: | 120 rit. . | 80 accel. | 100",
+            vec![
+                Key("tempo"),
+                Barline,
+                Num(120),
+                Ramp("rit."),
+                ExtendNote,
+                Barline,
+                Num(80),
+                Ramp("accel."),
+                Barline,
+                Num(100),
+            ],
+        );
+    }
+
+    #[test]
+    fn notes_are_not_ramps() {
+        lextest(
+            ":| a c c e",
+            vec![Key(""), Barline, Note("a"), Note("c"), Note("c"), Note("e")],
+        );
+    }
+
+    #[test]
+    fn lex_dynamics() {
+        lextest(
+            "dyn: | pp < . f | mf > ppp |",
+            vec![
+                Key("dyn"),
+                Barline,
+                Dynamic("pp"),
+                Ramp("<"),
+                ExtendNote,
+                Dynamic("f"),
+                Barline,
+                Dynamic("mf"),
+                Ramp(">"),
+                Dynamic("ppp"),
+                Barline,
+            ],
+        );
+    }
+
+    #[test]
+    fn dynamics_are_notes_in_other_staves() {
+        lextest(
+            ":| f ff",
+            vec![Key(""), Barline, Note("f"), Note("f"), Note("f")],
+        );
+    }
+
+    #[test]
+    fn lex_articulations() {
+        lextest(
+            ":| C* d'~ E_! F#,^ *part",
+            vec![
+                Key(""),
+                Barline,
+                Note("C*"),
+                Note("d'~"),
+                Note("E_!"),
+                Note("F#,^"),
+                PlayPart("part"),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_tuplets() {
+        lextest(
+            ":| C (3 D E F)2 (5",
+            vec![
+                Key(""),
+                Barline,
+                Note("C"),
+                StartTuplet(3),
+                Note("D"),
+                Note("E"),
+                Note("F"),
+                EndTuplet,
+                Num(2),
+                StartTuplet(5),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_chords() {
+        lextest(
+            ":| [CEG] [C E_ G']2 [Ga]* [1",
+            vec![
+                Key(""),
+                Barline,
+                StartChord("["),
+                Note("C"),
+                Note("E"),
+                Note("G"),
+                EndChord("]"),
+                StartChord("["),
+                Note("C"),
+                Note("E_"),
+                Note("G'"),
+                EndChord("]"),
+                Num(2),
+                StartChord("["),
+                Note("G"),
+                Note("a"),
+                EndChord("]*"),
+                Volta(1),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_chord_symbols() {
+        lextest(
+            "chords: | Cmaj7 . G7/B | F#dim 2 C9 Bbm7 D.C.",
+            vec![
+                Key("chords"),
+                Barline,
+                ChordName("Cmaj7"),
+                ExtendNote,
+                ChordName("G7/B"),
+                Barline,
+                ChordName("F#dim"),
+                Num(2),
+                ChordName("C9"),
+                ChordName("Bbm7"),
+                Navigation("D.C."),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_strums() {
+        lextest(
+            ":| v[CEG] ^[CE]!\nchords: | vC ^Am7/G",
+            vec![
+                Key(""),
+                Barline,
+                StartChord("v["),
+                Note("C"),
+                Note("E"),
+                Note("G"),
+                EndChord("]"),
+                StartChord("^["),
+                Note("C"),
+                Note("E"),
+                EndChord("]!"),
+                Barline,
+                Key("chords"),
+                Barline,
+                ChordName("vC"),
+                ChordName("^Am7/G"),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_ornaments() {
+        lextest(
+            ":| {d}c C~tr C~mor! c'~turn~ C~ {d e_ f}G",
+            vec![
+                Key(""),
+                Barline,
+                StartGrace,
+                Note("d"),
+                EndGrace,
+                Note("c"),
+                Note("C~tr"),
+                Note("C~mor!"),
+                Note("c'~turn~"),
+                Note("C~"),
+                StartGrace,
+                Note("d"),
+                Note("e_"),
+                Note("f"),
+                EndGrace,
+                Note("G"),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_bends() {
+        lextest(
+            "bend: | 0 / +2 | -0.5 - 12.25 |",
+            vec![
+                Key("bend"),
+                Barline,
+                Bend("0"),
+                Ramp("/"),
+                Bend("+2"),
+                Barline,
+                Bend("-0.5"),
+                Rest,
+                Bend("12.25"),
+                Barline,
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_glissandos() {
+        lextest(
+            ":| C/ G c/c, // comment",
+            vec![
+                Key(""),
+                Barline,
+                Note("C"),
+                Glissando,
+                Note("G"),
+                Note("c"),
+                Glissando,
+                Note("c,"),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_controller_staves() {
+        lextest(
+            "cc10: | 0 / . 127 |\ncc: | C/ G",
+            vec![
+                Key("cc10"),
+                Barline,
+                Num(0),
+                Ramp("/"),
+                ExtendNote,
+                Num(127),
+                Barline,
+                Barline,
+                Key("cc"),
+                Barline,
+                Note("C"),
+                Glissando,
+                Note("G"),
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_pedal_marks() {
+        lextest(
+            "ped: | v . ^ v2 | 64 |",
+            vec![
+                Key("ped"),
+                Barline,
+                Pedal("v"),
+                ExtendNote,
+                Pedal("^"),
+                Pedal("v"),
+                Num(2),
+                Barline,
+                Num(64),
+                Barline,
+            ],
+        );
+    }
+
+    #[test]
+    fn lex_pressure_staves() {
+        lextest(
+            "pressure: | / 100 |\npressure_C#: | 0 / 64",
+            vec![
+                Key("pressure"),
+                Barline,
+                Ramp("/"),
+                Num(100),
+                Barline,
+                Barline,
+                Key("pressure_C#"),
+                Barline,
+                Num(0),
+                Ramp("/"),
+                Num(64),
+            ],
+        );
+    }
 }
@@ -47,6 +47,26 @@ enum MeloCommand {
         )]
         ticks_per_beat: i16,
 
+        #[structopt(
+            long = "ramp-resolution",
+            help = "Tempo, expression or controller changes per beat during gradual changes.",
+            default_value = "8"
+        )]
+        ramp_resolution: u16,
+
+        #[structopt(
+            long = "dynamics-as-expression",
+            help = "Play dynamics by changing expression (CC11), rather than note velocities."
+        )]
+        dynamics_as_expression: bool,
+
+        #[structopt(
+            long = "bend-resolution",
+            help = "MIDI ticks between pitch bend messages during gradual bends and glissandos.",
+            default_value = "10"
+        )]
+        bend_resolution: u16,
+
         #[structopt(
             short = "o",
             long = "output",
@@ -79,6 +99,26 @@ enum MeloCommand {
         )]
         ticks_per_beat: i16,
 
+        #[structopt(
+            long = "ramp-resolution",
+            help = "Tempo, expression or controller changes per beat during gradual changes.",
+            default_value = "8"
+        )]
+        ramp_resolution: u16,
+
+        #[structopt(
+            long = "dynamics-as-expression",
+            help = "Play dynamics by changing expression (CC11), rather than note velocities."
+        )]
+        dynamics_as_expression: bool,
+
+        #[structopt(
+            long = "bend-resolution",
+            help = "MIDI ticks between pitch bend messages during gradual bends and glissandos.",
+            default_value = "10"
+        )]
+        bend_resolution: u16,
+
         #[structopt(
             long = "abcmidi",
             help = "First generate ABC, the convert that to MIDI and play. \
@@ -143,12 +183,20 @@ fn run_command(command: MeloCommand) -> Result<(), Error> {
             input,
             output,
             ticks_per_beat,
+            ramp_resolution,
+            dynamics_as_expression,
+            bend_resolution,
             abcmidi,
         } => {
             if abcmidi {
                 compile_to_midi_via_abc(&input, &output)
             } else {
-                let options = MidiGenerationOptions { ticks_per_beat };
+                let options = MidiGenerationOptions {
+                    ticks_per_beat,
+                    ramp_resolution,
+                    dynamics_as_expression,
+                    bend_resolution,
+                };
                 let midi = compile_to_midi(&input, &options)?;
                 write_binary(&midi, output)
             }
@@ -157,6 +205,9 @@ fn run_command(command: MeloCommand) -> Result<(), Error> {
         MeloCommand::Play {
             input,
             ticks_per_beat,
+            ramp_resolution,
+            dynamics_as_expression,
+            bend_resolution,
             abcmidi,
         } => {
             let mid_out = Temp::new_file()?;
@@ -164,7 +215,12 @@ fn run_command(command: MeloCommand) -> Result<(), Error> {
             if abcmidi {
                 compile_to_midi_via_abc(&input, &Some(&mid_out))?;
             } else {
-                let options = MidiGenerationOptions { ticks_per_beat };
+                let options = MidiGenerationOptions {
+                    ticks_per_beat,
+                    ramp_resolution,
+                    dynamics_as_expression,
+                    bend_resolution,
+                };
                 let midi = compile_to_midi(&input, &options)?;
                 write_binary(&midi, Some(&mid_out))?;
             }
@@ -1,12 +1,24 @@
 #[derive(Debug)]
 pub struct MidiGenerationOptions {
     pub ticks_per_beat: i16,
+
+    // The number of changes per beat used to play a `rit.`, `accel.`, hairpin or controller `/`.
+    pub ramp_resolution: u16,
+
+    // Whether dynamics change the expression (CC11) of each voice, rather than note velocities.
+    pub dynamics_as_expression: bool,
+
+    // The number of ticks between each pitch bend message during a gradual bend or glissando.
+    pub bend_resolution: u16,
 }
 
 impl Default for MidiGenerationOptions {
     fn default() -> Self {
         MidiGenerationOptions {
             ticks_per_beat: 480,
+            ramp_resolution: 8,
+            dynamics_as_expression: false,
+            bend_resolution: 10,
         }
     }
 }
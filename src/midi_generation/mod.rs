@@ -3,7 +3,155 @@ pub mod data;
 use self::data::*;
 
 use error::SourceMap;
+use notes::round;
+use parsing::data::{Articulation, Ornament, Strum, StrumDirection};
 use sequencing::data::*;
+use sequencing::play_ornaments;
+
+/// The tick at which a position in a voice occurs, taking into account the length of each bar.
+fn position_ticks(
+    piece: &Piece,
+    ticks_per_beat: u64,
+    divisions_per_bar: u32,
+    position: u32,
+) -> u64 {
+    let bar = position / divisions_per_bar;
+    let offset = u64::from(position % divisions_per_bar);
+    let ticks_per_bar = ticks_per_beat * piece.beats_in_bar(bar);
+    let ticks_per_division = ticks_per_bar / u64::from(divisions_per_bar);
+
+    ticks_per_beat * piece.beats_before_bar(bar) + ticks_per_division * offset
+}
+
+/// The number of MIDI clocks between metronome clicks, for a beat of the given length as a fraction
+/// of a whole note. There are 24 clocks in a quarter note.
+fn clocks_per_click((length, unit): (u64, u64)) -> u8 {
+    ::std::cmp::max(96 * length / unit, 1) as u8
+}
+
+/// The number of ticks a note sounds for, and its velocity, after applying its articulation.
+fn articulate(articulation: Option<Articulation>, len_ticks: u64, vel: u8) -> (u64, u8) {
+    use std::cmp::{max, min};
+
+    // Notes are normally released a tick early, so that repeated notes can be heard. Every note
+    // sounds for at least a tick, even when it is too short to be given one.
+    match articulation {
+        None => (max(len_ticks.saturating_sub(1), 1), vel),
+        Some(Articulation::Staccato) => (max(len_ticks / 2, 1), vel),
+        Some(Articulation::Tenuto) => (max(len_ticks, 1), vel),
+        Some(Articulation::Accent) => (max(len_ticks.saturating_sub(1), 1), min(vel + 20, 127)),
+        Some(Articulation::Marcato) => (max(len_ticks * 3 / 4, 1), min(vel + 30, 127)),
+    }
+}
+
+/// The number of notes strummed before each note, among those which start at the same position.
+fn strum_order(notes: &[Note], direction: StrumDirection) -> Vec<u64> {
+    let mut indices = (0..notes.len()).collect::<Vec<_>>();
+    indices.sort_by_key(|&index| (notes[index].position, notes[index].midi.midi()));
+
+    let mut order = vec![0; notes.len()];
+    let mut start = 0;
+    while start < indices.len() {
+        let position = notes[indices[start]].position;
+        let chord_size = indices[start..]
+            .iter()
+            .take_while(|&&index| notes[index].position == position)
+            .count();
+
+        for (rank, &index) in indices[start..start + chord_size].iter().enumerate() {
+            order[index] = match notes[index].strum.unwrap_or(direction) {
+                StrumDirection::Down => rank as u64,
+                StrumDirection::Up => (chord_size - 1 - rank) as u64,
+            };
+        }
+
+        start += chord_size;
+    }
+
+    order
+}
+
+/// Expand changes of a value into the steps which play them. Each change is given by its tick, the
+/// new value, and the tick at which it starts to change gradually towards that value, if it does.
+fn ramp_steps(
+    changes: &[(u64, u64, Option<u64>)],
+    initial_value: u64,
+    ticks_per_step: u64,
+) -> Vec<(u64, u64)> {
+    let mut current_value = initial_value;
+    let mut steps = Vec::new();
+
+    for &(pos_ticks, value, ramp_start_ticks) in changes {
+        if let Some(ramp_start_ticks) = ramp_start_ticks {
+            let ramp_ticks = pos_ticks - ramp_start_ticks;
+            let value_difference = value as f64 - current_value as f64;
+
+            let mut step_ticks = ramp_start_ticks + ticks_per_step;
+            while step_ticks < pos_ticks {
+                let progress = (step_ticks - ramp_start_ticks) as f64 / ramp_ticks as f64;
+                let step_value = current_value as f64 + value_difference * progress;
+
+                steps.push((step_ticks, round(step_value)));
+                step_ticks += ticks_per_step;
+            }
+        }
+
+        steps.push((pos_ticks, value));
+        current_value = value;
+    }
+
+    steps.dedup_by_key(|step| step.1);
+
+    steps
+}
+
+/// The pitch wheel value which bends by the given number of cents, when the whole wheel bends by
+/// the given number of semitones either way. Bends beyond the range go as far as they can.
+fn pitch_wheel_value(cents: i32, bend_range: u8) -> u64 {
+    let value = 8192 + i64::from(cents) * 8192 / (i64::from(bend_range) * 100);
+    value.clamp(0, 16383) as u64
+}
+
+/// Put steps from several sources in order, leaving out steps which don't change the value.
+fn merge_steps(mut steps: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    steps.sort_by_key(|step| step.0);
+    steps.dedup_by_key(|step| step.1);
+    steps
+}
+
+/// Expand changes of several separate values, such as different controllers, into the steps which
+/// play them. Each change is given by the value it belongs to, then as in `ramp_steps`. A value
+/// which begins by changing gradually changes from 0.
+fn keyed_ramp_steps<K: Copy + Ord>(
+    changes: &[(K, u64, u64, Option<u64>)],
+    ticks_per_step: u64,
+) -> Vec<(u64, K, u64)> {
+    let mut keys = changes.iter().map(|change| change.0).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    let mut steps = Vec::new();
+
+    for key in keys {
+        let mut key_changes = changes
+            .iter()
+            .filter(|change| change.0 == key)
+            .map(|&(_, pos_ticks, value, ramp_start_ticks)| (pos_ticks, value, ramp_start_ticks))
+            .collect::<Vec<_>>();
+
+        if let Some(&(_, _, Some(ramp_start_ticks))) = key_changes.first() {
+            key_changes.insert(0, (ramp_start_ticks, 0, None));
+        }
+
+        steps.extend(
+            ramp_steps(&key_changes, 0, ticks_per_step)
+                .into_iter()
+                .map(|(pos_ticks, value)| (pos_ticks, key, value)),
+        );
+    }
+
+    steps
+}
 
 pub fn generate_midi(
     piece: &Piece,
@@ -18,9 +166,18 @@ pub fn generate_midi(
         const VEL_STRONG: u8 = 95;
         const VEL_WEAK: u8 = 80;
 
-        let tempo = MICROSECONDS_PER_MIN / piece.tempo as u32;
+        // MIDI always measures tempo in quarter notes, whatever note the tempo of the piece counts.
+        let (tempo_beat_length, tempo_beat_unit) = piece.tempo_beat();
+        let microseconds_per_quarter = |tempo: u64| {
+            let microseconds = u64::from(MICROSECONDS_PER_MIN) * tempo_beat_unit;
+            (microseconds / (4 * tempo_beat_length * tempo)) as u32
+        };
+
+        let tempo = microseconds_per_quarter(piece.tempo);
+        let ticks_per_beat = options.ticks_per_beat as u64 * 4 / piece.beat_unit;
+        let denominator_power = piece.beat_unit.trailing_zeros() as u8;
 
-        let track0 = Track {
+        let mut track0 = Track {
             copyright: None,
             name: None,
             events: vec![
@@ -34,20 +191,118 @@ pub fn generate_midi(
                 },
                 TrackEvent {
                     vtime: 0,
-                    event: Event::Meta(MetaEvent::key_signature(0, 0)),
+                    event: Event::Meta(MetaEvent::key_signature(
+                        piece.key.sharps as u8,
+                        piece.key.minor as u8,
+                    )),
                 },
                 TrackEvent {
                     vtime: 0,
-                    event: Event::Meta(MetaEvent::time_signature(piece.beats as u8, 2, 48, 8)),
+                    event: Event::Meta(MetaEvent::time_signature(
+                        piece.beats as u8,
+                        denominator_power,
+                        clocks_per_click(piece.tempo_beat()),
+                        8,
+                    )),
                 },
             ],
         };
 
+        let key_changes = {
+            let mut key_changes = piece
+                .voices
+                .iter()
+                .flat_map(|voice| {
+                    let divisions_per_bar = voice.divisions_per_bar;
+                    voice.key_changes.iter().map(move |key_change| {
+                        let pos_ticks = position_ticks(
+                            piece,
+                            ticks_per_beat,
+                            divisions_per_bar,
+                            key_change.position,
+                        );
+                        (pos_ticks, key_change.key)
+                    })
+                }).collect::<Vec<_>>();
+
+            // Sort by start time
+            key_changes.sort_by_key(|key_change| key_change.0);
+            key_changes.dedup();
+
+            key_changes
+        };
+
+        // Each `rit.`, `accel.` or hairpin becomes a series of small changes.
+        let ramp_resolution = ::std::cmp::max(options.ramp_resolution, 1);
+        let ticks_per_step = ticks_per_beat * tempo_beat_length / u64::from(ramp_resolution);
+        let ticks_per_step = ::std::cmp::max(ticks_per_step, 1);
+
+        let tempo_changes = {
+            let mut tempo_changes = piece
+                .voices
+                .iter()
+                .flat_map(|voice| {
+                    let divisions_per_bar = voice.divisions_per_bar;
+                    voice.tempo_changes.iter().map(move |tempo_change| {
+                        let ticks = |position| {
+                            position_ticks(piece, ticks_per_beat, divisions_per_bar, position)
+                        };
+                        let pos_ticks = ticks(tempo_change.position);
+                        let ramp_start_ticks = tempo_change.ramp_start.map(ticks);
+                        (pos_ticks, tempo_change.tempo, ramp_start_ticks)
+                    })
+                }).collect::<Vec<_>>();
+
+            // Sort by start time
+            tempo_changes.sort_by_key(|tempo_change| tempo_change.0);
+            tempo_changes.dedup();
+
+            ramp_steps(&tempo_changes, piece.tempo, ticks_per_step)
+        };
+
+        let meter_changes = piece.meter_changes.iter().map(|meter_change| {
+            let pos_ticks = ticks_per_beat * piece.beats_before_bar(meter_change.bar);
+            let event = MetaEvent::time_signature(
+                meter_change.beats as u8,
+                denominator_power,
+                clocks_per_click(piece.felt_beat_in_bar(meter_change.bar)),
+                8,
+            );
+            (pos_ticks, event)
+        });
+
+        let meta_events = {
+            let mut meta_events = key_changes
+                .into_iter()
+                .map(|(pos_ticks, key)| {
+                    let event = MetaEvent::key_signature(key.sharps as u8, key.minor as u8);
+                    (pos_ticks, event)
+                }).chain(tempo_changes.iter().map(|&(pos_ticks, tempo)| {
+                    let event = MetaEvent::tempo_setting(microseconds_per_quarter(tempo));
+                    (pos_ticks, event)
+                })).chain(meter_changes)
+                .collect::<Vec<_>>();
+
+            // Sort by start time
+            meta_events.sort_by_key(|meta_event| meta_event.0);
+
+            meta_events
+        };
+
+        let mut cursor = 0;
+        for (pos_ticks, meta_event) in meta_events {
+            track0.events.push(TrackEvent {
+                vtime: pos_ticks - cursor,
+                event: Event::Meta(meta_event),
+            });
+
+            cursor = pos_ticks;
+        }
+
         let mut tracks = vec![track0];
 
         for voice in &piece.voices {
-            // Using +0.5 instead of round for wasm compatibility.
-            let volume = ((voice.volume.unwrap_or(1.0) * 127.0) + 0.5) as u8;
+            let volume = round(voice.volume.unwrap_or(1.0) * 127.0) as u8;
 
             let mut events = vec![
                 TrackEvent {
@@ -67,17 +322,70 @@ pub fn generate_midi(
                 },
             ];
 
+            for (controller, value) in voice.mix_controllers() {
+                events.push(TrackEvent {
+                    vtime: 0,
+                    event: Event::Midi(MidiMessage::control_change(
+                        controller,
+                        value,
+                        voice.channel - 1,
+                    )),
+                });
+            }
+
+            let notes = play_ornaments(voice, piece);
+
+            let glissandos = notes
+                .iter()
+                .filter_map(|note| match note.ornament {
+                    Some(Ornament::Glissando { semitones }) => Some((note, semitones)),
+                    _ => None,
+                }).collect::<Vec<_>>();
+
+            // The range of the pitch wheel is set with RPN 0, then the RPN is closed again.
+            if !voice.bend_changes.is_empty() || !glissandos.is_empty() {
+                let rpn = &[
+                    (101, 0),
+                    (100, 0),
+                    (6, voice.bend_range),
+                    (38, 0),
+                    (101, 127),
+                    (100, 127),
+                ];
+
+                for &(controller, value) in rpn {
+                    events.push(TrackEvent {
+                        vtime: 0,
+                        event: Event::Midi(MidiMessage::control_change(
+                            controller,
+                            value,
+                            voice.channel - 1,
+                        )),
+                    });
+                }
+            }
+
+            let strum_order = strum_order(&notes, voice.strum_direction);
+
             let split_notes = {
                 let mut split_notes = Vec::new();
 
-                for note in &voice.notes {
+                for (note, &strum_rank) in notes.iter().zip(&strum_order) {
                     let midi_note = note.midi.midi() as u8;
-                    let ticks_per_bar = options.ticks_per_beat as u64 * piece.beats;
-                    let ticks_per_division = ticks_per_bar / u64::from(voice.divisions_per_bar);
-                    let pos_ticks = ticks_per_division * u64::from(note.position);
-                    let len_ticks = ticks_per_division * u64::from(note.length);
-                    let vel = {
-                        let divisions_per_beat = voice.divisions_per_bar / piece.beats as u32;
+                    let ticks = |position| {
+                        position_ticks(piece, ticks_per_beat, voice.divisions_per_bar, position)
+                    };
+                    let pos_ticks = ticks(note.position);
+                    let len_ticks = ticks(note.position + note.length) - pos_ticks;
+                    let dynamic = if options.dynamics_as_expression {
+                        None
+                    } else {
+                        voice.dynamic_at(note.position)
+                    };
+                    let vel = note.velocity.or(dynamic).unwrap_or_else(|| {
+                        let bar = note.position / voice.divisions_per_bar;
+                        let beats = piece.felt_beats_in_bar(bar) as u32;
+                        let divisions_per_beat = voice.divisions_per_bar / beats;
                         let divisions_per_beat = ::std::cmp::max(divisions_per_beat, 1);
 
                         if note.position % voice.divisions_per_bar == 0 {
@@ -87,31 +395,174 @@ pub fn generate_midi(
                         } else {
                             VEL_WEAK
                         }
+                    });
+
+                    let (len_ticks, vel) = articulate(note.articulation, len_ticks, vel);
+
+                    // Each note of a strummed chord starts a little after the one before it, but
+                    // they are all released together.
+                    let strum_ticks = match voice.strum {
+                        Some(Strum::Ticks(ticks)) => u64::from(ticks),
+                        Some(Strum::Milliseconds(milliseconds)) => {
+                            let tempo = tempo_changes
+                                .iter()
+                                .take_while(|tempo_change| tempo_change.0 <= pos_ticks)
+                                .last()
+                                .map(|tempo_change| tempo_change.1)
+                                .unwrap_or(piece.tempo);
+                            let microseconds = u64::from(milliseconds) * 1000;
+                            microseconds * options.ticks_per_beat as u64
+                                / u64::from(microseconds_per_quarter(tempo))
+                        }
+                        None => 0,
                     };
+                    let strum_delay =
+                        ::std::cmp::min(strum_rank * strum_ticks, len_ticks.saturating_sub(1));
 
-                    let note_on = (true, midi_note, pos_ticks, vel);
-                    let note_off = (false, midi_note, pos_ticks + len_ticks - 1, 0);
+                    let note_on = (true, midi_note, pos_ticks + strum_delay, vel);
+                    let note_off = (false, midi_note, pos_ticks + len_ticks, 0);
                     split_notes.push(note_on);
                     split_notes.push(note_off);
                 }
 
+                split_notes
+            };
+
+            let expression_changes = if options.dynamics_as_expression {
+                let dynamic_changes = voice
+                    .dynamic_changes
+                    .iter()
+                    .map(|dynamic_change| {
+                        let ticks = |position| {
+                            position_ticks(piece, ticks_per_beat, voice.divisions_per_bar, position)
+                        };
+                        let pos_ticks = ticks(dynamic_change.position);
+                        let ramp_start_ticks = dynamic_change.ramp_start.map(ticks);
+                        let velocity = u64::from(dynamic_change.velocity);
+                        (pos_ticks, velocity, ramp_start_ticks)
+                    }).collect::<Vec<_>>();
+
+                ramp_steps(&dynamic_changes, u64::from(DEFAULT_DYNAMIC), ticks_per_step)
+            } else {
+                Vec::new()
+            };
+
+            let ticks = |position| {
+                position_ticks(piece, ticks_per_beat, voice.divisions_per_bar, position)
+            };
+
+            let controller_steps = {
+                let controller_changes = voice
+                    .controller_changes
+                    .iter()
+                    .map(|controller_change| {
+                        let pos_ticks = ticks(controller_change.position);
+                        let ramp_start_ticks = controller_change.ramp_start.map(ticks);
+                        let value = u64::from(controller_change.value);
+                        (controller_change.controller, pos_ticks, value, ramp_start_ticks)
+                    }).collect::<Vec<_>>();
+
+                keyed_ramp_steps(&controller_changes, ticks_per_step)
+            };
+
+            // The pressure of the whole channel is kept apart from the pressure of each note.
+            let pressure_steps = {
+                let pressure_changes = voice
+                    .pressure_changes
+                    .iter()
+                    .map(|pressure_change| {
+                        let note = pressure_change.note.map(|note| note.midi() as u8);
+                        let pos_ticks = ticks(pressure_change.position);
+                        let ramp_start_ticks = pressure_change.ramp_start.map(ticks);
+                        let pressure = u64::from(pressure_change.pressure);
+                        (note, pos_ticks, pressure, ramp_start_ticks)
+                    }).collect::<Vec<_>>();
+
+                keyed_ramp_steps(&pressure_changes, ticks_per_step)
+            };
+
+            // Gradual bends and glissandos move the pitch wheel in steps of a few ticks.
+            let ticks_per_bend_step = u64::from(::std::cmp::max(options.bend_resolution, 1));
+
+            let bend_steps = {
+                let wheel = |cents| pitch_wheel_value(cents, voice.bend_range);
+
+                let bend_changes = voice
+                    .bend_changes
+                    .iter()
+                    .map(|bend_change| {
+                        let pos_ticks = ticks(bend_change.position);
+                        let ramp_start_ticks = bend_change.ramp_start.map(ticks);
+                        (pos_ticks, wheel(bend_change.cents), ramp_start_ticks)
+                    }).collect::<Vec<_>>();
+
+                let mut bend_steps = ramp_steps(&bend_changes, wheel(0), ticks_per_bend_step);
+
+                // A glissando bends from its note until the next note begins, where the bend
+                // from the `bend:` staves takes over again.
+                for (note, semitones) in glissandos {
+                    let end = note.position + note.length;
+                    let pos_ticks = ticks(note.position);
+                    let end_ticks = ticks(end);
+                    let from = voice.bend_at(note.position);
+                    let to = from + i32::from(semitones) * 100;
+
+                    let glide = &[
+                        (pos_ticks, wheel(from), None),
+                        (end_ticks, wheel(to), Some(pos_ticks)),
+                    ];
+                    let mut glide_steps = ramp_steps(glide, wheel(from), ticks_per_bend_step);
+                    glide_steps.pop();
+
+                    bend_steps.extend(glide_steps);
+                    bend_steps.push((end_ticks, wheel(voice.bend_at(end))));
+                }
+
+                merge_steps(bend_steps)
+            };
+
+            let messages = {
+                let mut messages = expression_changes
+                    .into_iter()
+                    .map(|(pos_ticks, expression)| {
+                        let message =
+                            MidiMessage::control_change(11, expression as u8, voice.channel - 1);
+                        (pos_ticks, message)
+                    }).chain(controller_steps.into_iter().map(|(pos_ticks, controller, value)| {
+                        let message =
+                            MidiMessage::control_change(controller, value as u8, voice.channel - 1);
+                        (pos_ticks, message)
+                    })).chain(pressure_steps.into_iter().map(|(pos_ticks, note, pressure)| {
+                        let message = match note {
+                            Some(note) => MidiMessage::polyphonic_aftertouch(
+                                note,
+                                pressure as u8,
+                                voice.channel - 1,
+                            ),
+                            None => MidiMessage::channel_aftertouch(pressure as u8, voice.channel - 1),
+                        };
+                        (pos_ticks, message)
+                    })).chain(bend_steps.into_iter().map(|(pos_ticks, value)| {
+                        let (lsb, msb) = ((value & 0x7f) as u8, (value >> 7) as u8);
+                        (pos_ticks, MidiMessage::pitch_bend(lsb, msb, voice.channel - 1))
+                    })).chain(split_notes.into_iter().map(|(on, midi_note, pos_ticks, vel)| {
+                        let message = if on {
+                            MidiMessage::note_on(midi_note, vel, voice.channel - 1)
+                        } else {
+                            MidiMessage::note_off(midi_note, vel, voice.channel - 1)
+                        };
+                        (pos_ticks, message)
+                    })).collect::<Vec<_>>();
+
                 // Sort by start time
-                split_notes.sort_by_key(|note| note.2);
+                messages.sort_by_key(|message| message.0);
 
-                split_notes
+                messages
             };
 
             let mut cursor = 0;
-            for note in split_notes {
-                let (on, midi_note, pos_ticks, vel) = note;
+            for (pos_ticks, message) in messages {
                 let vtime = pos_ticks - cursor;
-                let message = {
-                    if on {
-                        MidiMessage::note_on(midi_note, vel, voice.channel - 1)
-                    } else {
-                        MidiMessage::note_off(midi_note, vel, voice.channel - 1)
-                    }
-                };
 
                 events.push(TrackEvent {
                     vtime,
@@ -145,3 +596,228 @@ pub fn generate_midi(
 
     Some(buffer)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_helpers::midi;
+
+    fn piece_in_meter(beats: u64, meter_changes: Vec<MeterChange>) -> Piece<'static> {
+        Piece {
+            title: None,
+            composer: None,
+            tempo: 120,
+            beats,
+            beat_unit: 4,
+            key: Default::default(),
+            meter_changes,
+            voices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn position_ticks_follow_meter_changes() {
+        let piece = piece_in_meter(4, vec![MeterChange { bar: 1, beats: 3 }]);
+
+        assert_eq!(position_ticks(&piece, 120, 12, 0), 0);
+        assert_eq!(position_ticks(&piece, 120, 12, 6), 240);
+        assert_eq!(position_ticks(&piece, 120, 12, 12), 480);
+        assert_eq!(position_ticks(&piece, 120, 12, 18), 660);
+        assert_eq!(position_ticks(&piece, 120, 12, 24), 840);
+    }
+
+    #[test]
+    fn metronome_clicks_on_each_felt_beat() {
+        assert_eq!(clocks_per_click((1, 4)), 24);
+        assert_eq!(clocks_per_click((1, 2)), 48);
+        assert_eq!(clocks_per_click((3, 8)), 36);
+        assert_eq!(clocks_per_click((3, 16)), 18);
+    }
+
+    #[test]
+    fn articulations_change_length_and_velocity() {
+        assert_eq!(articulate(None, 120, 80), (119, 80));
+        assert_eq!(articulate(Some(Articulation::Staccato), 120, 80), (60, 80));
+        assert_eq!(articulate(Some(Articulation::Tenuto), 120, 80), (120, 80));
+        assert_eq!(articulate(Some(Articulation::Accent), 120, 80), (119, 100));
+        assert_eq!(articulate(Some(Articulation::Marcato), 120, 80), (90, 110));
+        assert_eq!(articulate(Some(Articulation::Marcato), 120, 120), (90, 127));
+    }
+
+    #[test]
+    fn short_notes_sound_for_a_tick() {
+        let articulations = [
+            None,
+            Some(Articulation::Staccato),
+            Some(Articulation::Tenuto),
+            Some(Articulation::Accent),
+            Some(Articulation::Marcato),
+        ];
+
+        for &articulation in &articulations {
+            assert_eq!(articulate(articulation, 0, 80).0, 1);
+            assert_eq!(articulate(articulation, 1, 80).0, 1);
+        }
+    }
+
+    fn strummed_note(position: u32, num: i8, strum: Option<StrumDirection>) -> Note {
+        Note {
+            position,
+            length: 1,
+            midi: midi(num),
+            velocity: None,
+            articulation: None,
+            strum,
+            ornament: None,
+        }
+    }
+
+    #[test]
+    fn chords_strum_down_from_the_lowest_note() {
+        let notes = [
+            strummed_note(0, 64, None),
+            strummed_note(0, 60, None),
+            strummed_note(0, 67, None),
+        ];
+
+        assert_eq!(strum_order(&notes, StrumDirection::Down), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn chords_strum_up_from_the_highest_note() {
+        let notes = [
+            strummed_note(0, 64, None),
+            strummed_note(0, 60, None),
+            strummed_note(0, 67, None),
+            strummed_note(2, 62, Some(StrumDirection::Down)),
+            strummed_note(2, 65, Some(StrumDirection::Down)),
+        ];
+
+        assert_eq!(strum_order(&notes, StrumDirection::Up), vec![1, 2, 0, 0, 1]);
+    }
+
+    #[test]
+    fn single_notes_are_not_strummed() {
+        let notes = [strummed_note(0, 60, None), strummed_note(1, 60, None)];
+
+        assert_eq!(strum_order(&notes, StrumDirection::Down), vec![0, 0]);
+        assert_eq!(strum_order(&notes, StrumDirection::Up), vec![0, 0]);
+    }
+
+    #[test]
+    fn ramp_steps_are_evenly_spaced() {
+        assert_eq!(
+            ramp_steps(&[(100, 80, Some(0))], 120, 25),
+            vec![(25, 110), (50, 100), (75, 90), (100, 80)]
+        );
+        assert_eq!(
+            ramp_steps(&[(40, 60, None), (100, 0, Some(40))], 120, 20),
+            vec![(40, 60), (60, 40), (80, 20), (100, 0)]
+        );
+    }
+
+    #[test]
+    fn ramp_steps_end_on_each_change() {
+        assert_eq!(
+            ramp_steps(&[(100, 0, Some(0))], 100, 30),
+            vec![(30, 70), (60, 40), (90, 10), (100, 0)]
+        );
+        assert_eq!(
+            ramp_steps(&[(0, 120, None), (50, 60, None)], 100, 10),
+            vec![(0, 120), (50, 60)]
+        );
+    }
+
+    #[test]
+    fn zero_length_ramps_jump_to_their_value() {
+        assert_eq!(ramp_steps(&[(50, 60, Some(50))], 120, 10), vec![(50, 60)]);
+        assert_eq!(ramp_steps(&[(50, 60, Some(45))], 120, 10), vec![(50, 60)]);
+    }
+
+    #[test]
+    fn pitch_wheel_is_centred_without_a_bend() {
+        assert_eq!(pitch_wheel_value(0, 2), 8192);
+        assert_eq!(pitch_wheel_value(0, 24), 8192);
+    }
+
+    #[test]
+    fn pitch_wheel_follows_the_bend_range() {
+        assert_eq!(pitch_wheel_value(100, 2), 12288);
+        assert_eq!(pitch_wheel_value(-100, 2), 4096);
+        assert_eq!(pitch_wheel_value(600, 12), 12288);
+        assert_eq!(pitch_wheel_value(-200, 2), 0);
+        assert_eq!(pitch_wheel_value(200, 2), 16383);
+    }
+
+    #[test]
+    fn pitch_wheel_stops_at_the_bend_range() {
+        assert_eq!(pitch_wheel_value(-201, 2), 0);
+        assert_eq!(pitch_wheel_value(-2400, 2), 0);
+        assert_eq!(pitch_wheel_value(201, 2), 16383);
+        assert_eq!(pitch_wheel_value(2400, 2), 16383);
+    }
+
+    #[test]
+    fn merged_steps_are_ordered_without_repeated_values() {
+        let bend_steps = vec![(0, 8192), (30, 12288), (60, 8192)];
+        let glide_steps = vec![(10, 8192), (20, 10240)];
+
+        assert_eq!(
+            merge_steps(bend_steps.into_iter().chain(glide_steps).collect()),
+            vec![(0, 8192), (20, 10240), (30, 12288), (60, 8192)]
+        );
+    }
+
+    #[test]
+    fn controllers_ramp_separately() {
+        let changes = [
+            (11, 0, 100, None),
+            (7, 40, 80, Some(0)),
+            (11, 40, 60, Some(0)),
+        ];
+
+        assert_eq!(
+            keyed_ramp_steps(&changes, 20),
+            vec![
+                (0, 7, 0),
+                (20, 7, 40),
+                (40, 7, 80),
+                (0, 11, 100),
+                (20, 11, 80),
+                (40, 11, 60),
+            ]
+        );
+    }
+
+    #[test]
+    fn controllers_which_begin_gradually_start_from_zero() {
+        assert_eq!(
+            keyed_ramp_steps(&[(64, 60, 40, Some(20)), (64, 80, 0, None)], 10),
+            vec![(20, 64, 0), (30, 64, 10), (40, 64, 20), (50, 64, 30), (60, 64, 40), (80, 64, 0)]
+        );
+    }
+
+    #[test]
+    fn note_pressures_ramp_apart_from_channel_pressure() {
+        let changes = [
+            (None, 40, 100, Some(0)),
+            (Some(64), 0, 20, None),
+            (Some(64), 40, 60, Some(0)),
+            (Some(67), 20, 127, Some(0)),
+        ];
+
+        assert_eq!(
+            keyed_ramp_steps(&changes, 20),
+            vec![
+                (0, None, 0),
+                (20, None, 50),
+                (40, None, 100),
+                (0, Some(64), 20),
+                (20, Some(64), 40),
+                (40, Some(64), 60),
+                (0, Some(67), 0),
+                (20, Some(67), 127),
+            ]
+        );
+    }
+}
@@ -73,8 +73,13 @@ impl Midi {
     }
 
     pub fn from_note(note: &str) -> Option<Midi> {
+        Midi::from_note_in_key(note, KeySignature::default())
+    }
+
+    pub fn from_note_in_key(note: &str, key: KeySignature) -> Option<Midi> {
         let mut chars = note.chars();
-        let mut midi: i64 = match chars.next().unwrap_or_default() {
+        let letter = chars.next().unwrap_or_default();
+        let mut midi: i64 = match letter {
             'A' => 57,
             'B' => 59,
             'C' => 60,
@@ -92,6 +97,11 @@ impl Midi {
             _ => return None,
         };
 
+        let explicit_accidental = note.contains(&['#', '_', '='][..]);
+        if !explicit_accidental {
+            midi += i64::from(key.accidental(letter)?);
+        }
+
         for ch in chars {
             let delta = match ch {
                 '#' => 1,
@@ -125,10 +135,333 @@ impl Midi {
     }
 }
 
-pub fn lcm(a: u32, b: u32) -> u32 {
-    if a == 0 || b == 0 {
-        return 0;
+/// A key signature, stored as its position on the circle of fifths.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct KeySignature {
+    pub sharps: i8,
+    pub minor: bool,
+}
+
+const MAJOR_KEY_NAMES: [&str; 15] = [
+    "Cb", "Gb", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#",
+];
+
+const MINOR_KEY_NAMES: [&str; 15] = [
+    "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#", "G#", "D#", "A#",
+];
+
+impl KeySignature {
+    /// Parses key names like `D major`, `F# minor`, `B_ major` or just `G`.
+    pub fn from_name(name: &str) -> Option<KeySignature> {
+        let mut words = name.split_whitespace();
+        let tonic = words.next()?;
+        let minor = match words.next() {
+            None | Some("major") => false,
+            Some("minor") => true,
+            _ => return None,
+        };
+
+        if words.next().is_some() {
+            return None;
+        }
+
+        let mut chars = tonic.chars();
+        let mut fifths: i8 = match chars.next()?.to_ascii_uppercase() {
+            'F' => -1,
+            'C' => 0,
+            'G' => 1,
+            'D' => 2,
+            'A' => 3,
+            'E' => 4,
+            'B' => 5,
+            _ => return None,
+        };
+
+        for ch in chars {
+            fifths += match ch {
+                '#' => 7,
+                '_' => -7,
+                _ => return None,
+            };
+        }
+
+        let sharps = if minor { fifths - 3 } else { fifths };
+
+        if sharps.abs() <= 7 {
+            Some(KeySignature { sharps, minor })
+        } else {
+            None
+        }
+    }
+
+    /// The number of semitones the key implicitly applies to a note letter.
+    pub fn accidental(self, letter: char) -> Option<i8> {
+        let fifths: i8 = match letter.to_ascii_uppercase() {
+            'F' => -1,
+            'C' => 0,
+            'G' => 1,
+            'D' => 2,
+            'A' => 3,
+            'E' => 4,
+            'B' => 5,
+            _ => return None,
+        };
+
+        if self.sharps >= fifths + 2 {
+            Some(1)
+        } else if self.sharps <= fifths - 6 {
+            Some(-1)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// The number of semitones from a note to the next note of the key's scale above or below it.
+    pub fn scale_step(self, midi: Midi, up: bool) -> i8 {
+        const MAJOR_SCALE: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+        // Minor keys share the notes of their relative major.
+        let tonic = i32::from(self.sharps) * 7;
+        let direction = if up { 1 } else { -1 };
+        let in_scale = |semitones: i8| {
+            let degree = (i32::from(midi.0) + i32::from(semitones) - tonic).rem_euclid(12);
+            MAJOR_SCALE.contains(&degree)
+        };
+
+        if in_scale(direction) {
+            direction
+        } else {
+            direction * 2
+        }
+    }
+
+    pub fn to_abc(self) -> String {
+        let index = (self.sharps + 7) as usize;
+        if self.minor {
+            format!("{}m", MINOR_KEY_NAMES[index])
+        } else {
+            MAJOR_KEY_NAMES[index].to_owned()
+        }
+    }
+}
+
+const DYNAMICS: [(&str, u8); 8] = [
+    ("ppp", 16),
+    ("pp", 33),
+    ("p", 49),
+    ("mp", 64),
+    ("mf", 80),
+    ("f", 96),
+    ("ff", 112),
+    ("fff", 126),
+];
+
+/// The velocity played for a dynamic marking like `mf`.
+pub fn dynamic_velocity(marking: &str) -> Option<u8> {
+    DYNAMICS
+        .iter()
+        .find(|&&(name, _)| name == marking)
+        .map(|&(_, velocity)| velocity)
+}
+
+/// The dynamic marking which is played with the given velocity, if there is one.
+pub fn dynamic_marking(velocity: u8) -> Option<&'static str> {
+    DYNAMICS
+        .iter()
+        .find(|&&(_, marking_velocity)| marking_velocity == velocity)
+        .map(|&(name, _)| name)
+}
+
+/// The number of the MIDI controller changed by a stave with the given prefix, such as `cc10:`.
+pub fn controller_number(prefix: &str) -> Option<i64> {
+    let digits = prefix.get(2..).filter(|_| prefix.starts_with("cc"))?;
+
+    if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// The note whose pressure is changed by a stave with the given prefix, such as `pressure_C#:`.
+pub fn pressure_note(prefix: &str) -> Option<Midi> {
+    prefix.strip_prefix("pressure_").and_then(Midi::from_note)
+}
+
+/// The intervals above the root for each chord quality, as written after the root of a chord
+/// symbol.
+const CHORD_QUALITIES: &[(&str, &[u8])] = &[
+    ("", &[0, 4, 7]),
+    ("m", &[0, 3, 7]),
+    ("min", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("aug", &[0, 4, 8]),
+    ("+", &[0, 4, 8]),
+    ("sus2", &[0, 2, 7]),
+    ("sus4", &[0, 5, 7]),
+    ("sus", &[0, 5, 7]),
+    ("5", &[0, 7]),
+    ("6", &[0, 4, 7, 9]),
+    ("m6", &[0, 3, 7, 9]),
+    ("7", &[0, 4, 7, 10]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("M7", &[0, 4, 7, 11]),
+    ("m7", &[0, 3, 7, 10]),
+    ("mmaj7", &[0, 3, 7, 11]),
+    ("m7b5", &[0, 3, 6, 10]),
+    ("dim7", &[0, 3, 6, 9]),
+    ("7sus4", &[0, 5, 7, 10]),
+    ("add9", &[0, 4, 7, 14]),
+    ("9", &[0, 4, 7, 10, 14]),
+    ("maj9", &[0, 4, 7, 11, 14]),
+    ("m9", &[0, 3, 7, 10, 14]),
+    ("11", &[0, 4, 7, 10, 14, 17]),
+    ("m11", &[0, 3, 7, 10, 14, 17]),
+    ("13", &[0, 4, 7, 10, 14, 21]),
+    ("maj13", &[0, 4, 7, 11, 14, 21]),
+    ("m13", &[0, 3, 7, 10, 14, 21]),
+];
+
+/// How the notes of a chord symbol are arranged when it is played.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Voicing {
+    /// The chord tones stacked upwards from the root.
+    Close,
+    /// A close voicing with every other note above the root raised by an octave.
+    Open,
+    /// A close voicing with its second highest note dropped by an octave.
+    Drop2,
+    /// Only the root, or the bass note of a slash chord.
+    Root,
+}
+
+impl Voicing {
+    pub fn from_name(name: &str) -> Option<Voicing> {
+        match name {
+            "close" => Some(Voicing::Close),
+            "open" => Some(Voicing::Open),
+            "drop2" => Some(Voicing::Drop2),
+            "root" => Some(Voicing::Root),
+            _ => None,
+        }
+    }
+}
+
+/// A chord written as a symbol like `Cmaj7`, `G7/B` or `F#dim`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChordSymbol {
+    // Pitch classes, in semitones above C.
+    pub root: u8,
+    pub bass: Option<u8>,
+
+    pub intervals: &'static [u8],
+}
+
+/// The pitch class of a note name like `E`, `F#`, `Bb` or `B_`, in semitones above C.
+fn pitch_class(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    let natural: i8 = match chars.next()? {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+
+    let accidental = match chars.as_str() {
+        "" => 0,
+        "#" => 1,
+        "b" | "_" => -1,
+        _ => return None,
+    };
+
+    Some(((natural + accidental + 12) % 12) as u8)
+}
+
+impl ChordSymbol {
+    pub fn from_symbol(symbol: &str) -> Option<ChordSymbol> {
+        let (chord, bass) = match symbol.find('/') {
+            Some(slash) => (&symbol[..slash], Some(pitch_class(&symbol[(slash + 1)..])?)),
+            None => (symbol, None),
+        };
+
+        let root_length = match chord.get(1..2) {
+            Some("#") | Some("b") | Some("_") => 2,
+            _ => 1,
+        };
+
+        let root = pitch_class(chord.get(..root_length)?)?;
+        let intervals = CHORD_QUALITIES
+            .iter()
+            .find(|&&(quality, _)| quality == &chord[root_length..])
+            .map(|&(_, intervals)| intervals)?;
+
+        Some(ChordSymbol {
+            root,
+            bass,
+            intervals,
+        })
     }
+
+    /// The notes played for the chord, with its root placed in the given octave relative to
+    /// middle C. A slash chord's bass note is played below the rest of the chord.
+    pub fn voiced(self, voicing: Voicing, octave: i8) -> Option<Vec<Midi>> {
+        let octave_start = 60 + 12 * i32::from(octave);
+        let root = octave_start + i32::from(self.root);
+
+        let mut notes = self
+            .intervals
+            .iter()
+            .map(|&interval| root + i32::from(interval))
+            .collect::<Vec<_>>();
+
+        match voicing {
+            Voicing::Close => (),
+            Voicing::Open => {
+                for note in notes.iter_mut().skip(1).step_by(2) {
+                    *note += 12;
+                }
+            }
+            Voicing::Drop2 => {
+                if notes.len() >= 3 {
+                    let second_highest = notes.len() - 2;
+                    notes[second_highest] -= 12;
+                }
+            }
+            Voicing::Root => {
+                notes = vec![octave_start + i32::from(self.bass.unwrap_or(self.root))];
+            }
+        }
+
+        notes.sort();
+
+        if let Some(bass) = self.bass {
+            if voicing != Voicing::Root {
+                let lowest = notes[0];
+                let distance = match (lowest - octave_start - i32::from(bass)).rem_euclid(12) {
+                    0 => 12,
+                    distance => distance,
+                };
+                notes.insert(0, lowest - distance);
+            }
+        }
+
+        notes
+            .into_iter()
+            .map(|note| {
+                if (0..128).contains(&note) {
+                    Some(Midi(note as i8))
+                } else {
+                    None
+                }
+            }).collect()
+    }
+}
+
+pub fn gcd(a: u32, b: u32) -> u32 {
     let mut ra = a;
     let mut rb = b;
     while rb != 0 {
@@ -136,7 +469,20 @@ pub fn lcm(a: u32, b: u32) -> u32 {
         rb = ra % rb;
         ra = t;
     }
-    (a / ra) * b
+    ra
+}
+
+pub fn lcm(a: u32, b: u32) -> u32 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    (a / gcd(a, b)) * b
+}
+
+/// Round a value which isn't negative to the nearest whole number.
+pub fn round(value: f64) -> u64 {
+    // Using +0.5 instead of round for wasm compatibility.
+    (value + 0.5) as u64
 }
 
 #[cfg(test)]
@@ -217,6 +563,92 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_note_in_key_to_midi() {
+        fn test(note: &str, key: &str, midi: i8) {
+            let key = KeySignature::from_name(key).unwrap();
+            assert_eq!(Midi::from_note_in_key(note, key), Midi::from_raw(midi));
+        }
+
+        test("F", "D major", 66);
+        test("C", "D major", 61);
+        test("G", "D major", 67);
+        test("F=", "D major", 65);
+        test("F_", "D major", 64);
+        test("G", "F# minor", 68);
+        test("D", "F# minor", 62);
+        test("B", "B_ major", 58);
+        test("e", "B_ major", 75);
+        test("a,", "B_ major", 57);
+        test("B", "C minor", 58);
+        test("A", "C minor", 56);
+        test("F", "C# major", 66);
+        test("B", "C# major", 60);
+    }
+
+    #[test]
+    fn test_scale_steps() {
+        fn test(note: &str, key: &str, up: i8, down: i8) {
+            let key = KeySignature::from_name(key).unwrap();
+            let midi = Midi::from_note_in_key(note, key).unwrap();
+            assert_eq!(key.scale_step(midi, true), up);
+            assert_eq!(key.scale_step(midi, false), down);
+        }
+
+        test("C", "C major", 2, -1);
+        test("E", "C major", 1, -2);
+        test("B", "C major", 1, -2);
+        test("C#", "C major", 1, -1);
+        test("C", "D major", 1, -2);
+        test("B", "B_ major", 2, -1);
+        test("G", "A minor", 2, -2);
+        test("E", "C minor", 2, -1);
+    }
+
+    #[test]
+    fn test_key_names() {
+        fn test(name: &str, sharps: i8, minor: bool, abc: &str) {
+            let key = KeySignature::from_name(name).unwrap();
+            assert_eq!(key, KeySignature { sharps, minor });
+            assert_eq!(key.to_abc(), abc);
+        }
+
+        test("C", 0, false, "C");
+        test("C major", 0, false, "C");
+        test("A minor", 0, true, "Am");
+        test("D major", 2, false, "D");
+        test("F# minor", 3, true, "F#m");
+        test("B_ major", -2, false, "Bb");
+        test("e minor", 1, true, "Em");
+        test("C_ major", -7, false, "Cb");
+        test("A# minor", 7, true, "A#m");
+
+        assert_eq!(KeySignature::from_name("H major"), None);
+        assert_eq!(KeySignature::from_name("D dorian"), None);
+        assert_eq!(KeySignature::from_name("G# major"), None);
+        assert_eq!(KeySignature::from_name(""), None);
+    }
+
+    #[test]
+    fn test_controller_numbers() {
+        assert_eq!(controller_number("cc10"), Some(10));
+        assert_eq!(controller_number("cc0"), Some(0));
+        assert_eq!(controller_number("cc200"), Some(200));
+        assert_eq!(controller_number("cc"), None);
+        assert_eq!(controller_number("cc1a"), None);
+        assert_eq!(controller_number("c10"), None);
+        assert_eq!(controller_number("dyn"), None);
+    }
+
+    #[test]
+    fn test_pressure_notes() {
+        assert_eq!(pressure_note("pressure_C"), Some(Midi(60)));
+        assert_eq!(pressure_note("pressure_f#'"), Some(Midi(90)));
+        assert_eq!(pressure_note("pressure_H"), None);
+        assert_eq!(pressure_note("pressure"), None);
+        assert_eq!(pressure_note("C"), None);
+    }
+
     #[test]
     fn test_lcm() {
         fn test(a: u32, b: u32, expected: u32) {
@@ -235,4 +667,51 @@ mod tests {
         test(2, 64, 64);
         test(7, 11, 77);
     }
+
+    #[test]
+    fn test_round() {
+        assert_eq!(round(0.0), 0);
+        assert_eq!(round(0.49), 0);
+        assert_eq!(round(0.5), 1);
+        assert_eq!(round(126.6), 127);
+    }
+
+    #[test]
+    fn dynamics_round_trip() {
+        assert_eq!(dynamic_velocity("mf"), Some(80));
+        assert_eq!(dynamic_velocity("mff"), None);
+        assert_eq!(dynamic_marking(80), Some("mf"));
+        assert_eq!(dynamic_marking(81), None);
+
+        for &(name, velocity) in &DYNAMICS {
+            assert_eq!(dynamic_marking(dynamic_velocity(name).unwrap()), Some(name));
+            assert_eq!(dynamic_velocity(dynamic_marking(velocity).unwrap()), Some(velocity));
+        }
+    }
+
+    #[test]
+    fn chord_symbols() {
+        fn test(symbol: &str, voicing: Voicing, expected: &[i8]) {
+            let chord = ChordSymbol::from_symbol(symbol).unwrap();
+            let notes = chord.voiced(voicing, 0).unwrap();
+            let midis = notes.iter().map(|note| note.midi()).collect::<Vec<_>>();
+            assert_eq!(midis, expected);
+        }
+
+        test("C", Voicing::Close, &[60, 64, 67]);
+        test("Cmaj7", Voicing::Close, &[60, 64, 67, 71]);
+        test("Am9", Voicing::Close, &[69, 72, 76, 79, 83]);
+        test("F#dim", Voicing::Close, &[66, 69, 72]);
+        test("Bbm7", Voicing::Close, &[70, 73, 77, 80]);
+        test("G7/B", Voicing::Close, &[59, 67, 71, 74, 77]);
+        test("Cmaj7", Voicing::Open, &[60, 67, 76, 83]);
+        test("Cmaj7", Voicing::Drop2, &[55, 60, 64, 71]);
+        test("Cmaj7", Voicing::Root, &[60]);
+        test("G7/B", Voicing::Root, &[71]);
+
+        assert_eq!(ChordSymbol::from_symbol("H7"), None);
+        assert_eq!(ChordSymbol::from_symbol("Cmaj8"), None);
+        assert_eq!(ChordSymbol::from_symbol("C/X"), None);
+        assert_eq!(ChordSymbol::from_symbol("C13").unwrap().voiced(Voicing::Open, 4), None);
+    }
 }
@@ -1,5 +1,5 @@
 use error::SourceLoc;
-use notes::Midi;
+use notes::{ChordSymbol, KeySignature, Midi, Voicing};
 use std::borrow::Cow;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -13,9 +13,15 @@ pub struct PieceNode<'a> {
     pub composer: Option<&'a str>,
     pub tempo: Option<u64>,
     pub beats: Option<u64>,
+    pub beat_unit: Option<u64>,
+    pub key: Option<KeySignature>,
+    pub arrangement: Vec<&'a str>,
+    pub arrangement_locs: Vec<SourceLoc>,
 
     pub voices: Vec<VoiceNode<'a>>,
+    pub parts: Vec<PartNode<'a>>,
     pub plays: Vec<PlayNode<'a>>,
+    pub sections: Vec<SectionNode<'a>>,
 }
 
 #[derive(Debug, Default, PartialEq, Eq)]
@@ -25,48 +31,225 @@ pub struct VoiceNode<'a> {
     pub channel: Option<u8>,
     pub transpose: Option<i8>,
     pub volume: Option<u8>,
+    pub voicing: Option<Voicing>,
+    pub chord_octave: Option<i8>,
+    pub arpeggio: Option<Arpeggio>,
+    pub arp_rate: Option<u32>,
+    pub arp_seed: Option<u64>,
+    pub strum: Option<Strum>,
+    pub strum_direction: Option<StrumDirection>,
+    pub bend_range: Option<u8>,
+    pub pan: Option<i8>,
+    pub reverb: Option<u8>,
+    pub chorus: Option<u8>,
+    pub expression: Option<u8>,
+    pub portamento: Option<u8>,
 }
 
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct PlayNode<'a> {
     pub voice: Option<&'a str>,
     pub staves: Vec<StaveNode<'a>>,
+    pub key_changes: Vec<KeyChangeNode>,
+    pub tempo_changes: Vec<TempoChangeNode>,
+    pub meter_changes: Vec<MeterChangeNode>,
     pub error_loc: Option<SourceLoc>,
 }
 
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PartNode<'a> {
+    pub name: &'a str,
+    pub staves: Vec<StaveNode<'a>>,
+    pub error_loc: Option<SourceLoc>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SectionNode<'a> {
+    pub name: &'a str,
+    pub plays: Vec<PlayNode<'a>>,
+    pub error_loc: Option<SourceLoc>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyChangeNode {
+    pub bar: usize,
+    pub key: KeySignature,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TempoChangeNode {
+    pub bar: usize,
+    pub tempo: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MeterChangeNode {
+    pub bar: usize,
+    pub beats: u64,
+}
+
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct StaveNode<'a> {
     pub prefix: Cow<'a, str>,
-    pub bars: Vec<BarTypeNode>,
+    pub bars: Vec<BarTypeNode<'a>>,
     pub bar_locs: Vec<SourceLoc>,
+    pub marks: Vec<MarkNode>,
+    pub mark_locs: Vec<SourceLoc>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MarkNode {
+    pub bar: usize,
+    pub mark: Mark,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mark {
+    StartRepeat,
+    EndRepeat { count: u32 },
+    Volta { number: u32 },
+    Segno,
+    Coda,
+    Fine,
+    DaCapo { until: Until },
+    DalSegno { until: Until },
+}
+
+/// A mark written after a note, such as the `*` in `C*`, which changes how it is played.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Articulation {
+    Staccato,
+    Tenuto,
+    Accent,
+    Marcato,
+}
+
+/// The mark in a value stave, such as `rit.` or `<`, which changes gradually towards the next
+/// value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RampKind {
+    Ritardando,
+    Accelerando,
+    Crescendo,
+    Diminuendo,
+    Slide,
+}
+
+/// A mark written after a note, such as the `~tr` in `C~tr`, which is played as a few short notes
+/// around it. Each neighbouring note is given as a number of semitones from the decorated note.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Ornament {
+    Trill { upper: i8 },
+    Mordent { lower: i8 },
+    Turn { upper: i8, lower: i8 },
+
+    // Slides in pitch towards the next note, which is the given number of semitones away.
+    Glissando { semitones: i8 },
+
+    // Marks a grace note, which is played before the note that it shares a position with.
+    GraceNote,
+}
+
+/// The order in which a voice plays the notes of each chord, when it is set to arpeggiate them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Arpeggio {
+    Up,
+    Down,
+    UpDown,
+    Random,
+}
+
+/// The delay between the start of each note of a strummed chord.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Strum {
+    Ticks(u32),
+    Milliseconds(u32),
+}
+
+/// The order in which the notes of a chord are strummed. Down plays the lowest note first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StrumDirection {
+    Down,
+    Up,
+}
+
+/// Where playback stops after a `D.C.` or `D.S.` jump.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Until {
+    End,
+    Fine,
+    Coda,
 }
 
 #[derive(Debug, PartialEq, Eq)]
-pub enum BarTypeNode {
+pub enum BarTypeNode<'a> {
     Bar(BarNode),
     RepeatBar,
+    PlayPart(PlayPartNode<'a>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PlayPartNode<'a> {
+    pub name: &'a str,
+    pub transpose: i8,
+    pub retrograde: bool,
+    pub augmentation: u32,
 }
 
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct BarNode {
     pub notes: Vec<NoteNode>,
     pub note_locs: Vec<SourceLoc>,
+    pub tuplets: Vec<TupletNode>,
 }
 
+/// A group of notes which are fitted into the length of a single note, like `(3 C D E)`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TupletNode {
+    // The range of notes in the bar which are part of the tuplet.
+    pub start: usize,
+    pub end: usize,
+
+    pub length: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NoteNode {
     Rest { length: u8 },
     Extension { length: u8 },
-    Note { length: u8, midi: Midi },
+    Note {
+        length: u8,
+        midi: Midi,
+        articulation: Option<Articulation>,
+        ornament: Option<Ornament>,
+        grace_notes: Vec<Midi>,
+    },
+    Chord {
+        length: u8,
+        midis: Vec<Midi>,
+        articulation: Option<Articulation>,
+        strum: Option<StrumDirection>,
+    },
+    ChordSymbol {
+        length: u8,
+        symbol: ChordSymbol,
+        strum: Option<StrumDirection>,
+    },
+    Value { length: u8, value: i64 },
+    Ramp { length: u8, kind: RampKind },
 }
 
 impl NoteNode {
     // TODO(***realname***): This is an inelegant way to have a common field.
-    pub fn length(self) -> u32 {
-        match self {
+    pub fn length(&self) -> u32 {
+        match *self {
             NoteNode::Rest { length }
             | NoteNode::Extension { length }
-            | NoteNode::Note { length, .. } => u32::from(length),
+            | NoteNode::Note { length, .. }
+            | NoteNode::Chord { length, .. }
+            | NoteNode::ChordSymbol { length, .. }
+            | NoteNode::Value { length, .. }
+            | NoteNode::Ramp { length, .. } => u32::from(length),
         }
     }
 }
@@ -29,6 +29,10 @@ pub enum ErrorType {
         stave_prefix: String,
     },
 
+    NoteInValueStave {
+        stave_prefix: String,
+    },
+
     InvalidAttribute {
         attribute: String,
         structure: &'static str,
@@ -54,6 +58,131 @@ pub enum ErrorType {
         placement: &'static str,
     },
 
+    ExcessNotesInPartBar,
+
+    NothingToDitto,
+
+    InvalidRepeatCount {
+        count: i64,
+    },
+
+    InvalidVoltaNumber {
+        number: i64,
+    },
+
+    InvalidTuplet {
+        divisions: i64,
+    },
+
+    NestedTuplet,
+
+    UnfinishedTuplet,
+
+    MismatchedTuplet {
+        divisions: i64,
+        length: u32,
+    },
+
+    EmptyChord,
+
+    InvalidChordSymbol {
+        symbol: String,
+    },
+
+    InvalidVoicing {
+        voicing: String,
+    },
+
+    InvalidArpeggio {
+        arpeggio: String,
+    },
+
+    InvalidArpRate {
+        rate: i64,
+    },
+
+    InvalidStrum {
+        strum: i64,
+    },
+
+    InvalidStrumDirection {
+        direction: String,
+    },
+
+    InvalidBendRange {
+        range: i64,
+    },
+
+    InvalidPan {
+        pan: i64,
+    },
+
+    ArticulationInChord {
+        note: String,
+    },
+
+    OrnamentInChord {
+        note: String,
+    },
+
+    EmptyGraceNotes,
+
+    DecoratedGraceNote {
+        note: String,
+    },
+
+    MisplacedGlissando,
+
+    InvalidPartModifiers {
+        part: String,
+    },
+
+    InvalidKeySignature {
+        key: String,
+    },
+
+    LateKeySignature,
+
+    InvalidTempo {
+        tempo: i64,
+    },
+
+    InvalidBeats {
+        beats: i64,
+    },
+
+    InvalidBeatUnit {
+        unit: i64,
+    },
+
+    InvalidVelocity {
+        velocity: i64,
+    },
+
+    InvalidBend {
+        bend: String,
+    },
+
+    InvalidController {
+        controller: i64,
+    },
+
+    InvalidControllerValue {
+        value: i64,
+    },
+
+    InvalidPressure {
+        pressure: i64,
+    },
+
+    InvalidPressureNote {
+        stave_prefix: String,
+    },
+
+    UnfinishedRamp {
+        ramp: String,
+    },
+
     MultipleParsingErrors {
         errors: Vec<ParsingError>,
     },
@@ -118,6 +247,12 @@ impl Display for ParsingError {
                             stave_prefix)
                 }
 
+                NoteInValueStave { ref stave_prefix } =>
+                {
+                    format!("Notes cannot be used in `{}:` staves. They only contain values, rests and dots.",
+                            stave_prefix)
+                }
+
                 // TODO(***realname***): which ones are valid?
                 InvalidAttribute {
                     ref attribute,
@@ -153,6 +288,197 @@ impl Display for ParsingError {
                     format!("Unexpected notes {} repeat sign `%`. Bars with repeat signs should contain nothing else.", placement)
                 }
 
+                ExcessNotesInPartBar =>
+                {
+                    "Unexpected notes in a bar which plays a part. Bars which play parts should contain nothing else.".to_owned()
+                }
+
+                NothingToDitto => "There is no previous note to repeat.".to_owned(),
+
+                InvalidRepeatCount { count } => format!(
+                    "Invalid repeat count `{}`. Repeats must be played between 1 and 255 times.",
+                    count
+                ),
+
+                InvalidVoltaNumber { number } => format!(
+                    "Invalid ending `[{}`. Endings must be numbered between 1 and 255.",
+                    number
+                ),
+
+                InvalidTuplet { divisions } => format!(
+                    "Invalid tuplet `({}`. Tuplets must contain notes with a total length between 1 and 255.",
+                    divisions
+                ),
+
+                NestedTuplet => "Tuplets cannot be placed inside other tuplets.".to_owned(),
+
+                UnfinishedTuplet =>
+                {
+                    "This tuplet is not closed. A tuplet must end with `)` in the same bar that it starts.".to_owned()
+                }
+
+                MismatchedTuplet { divisions, length } =>
+                {
+                    format!("The notes in tuplet `({}` have a total length of {}, but should have a total length of {}.",
+                            divisions,
+                            length,
+                            divisions)
+                }
+
+                EmptyChord => "Chords must contain at least one note.".to_owned(),
+
+                InvalidChordSymbol { ref symbol } =>
+                {
+                    format!("Invalid chord symbol `{}`. Expected a chord like `C`, `Am7`, `F#dim` or `G7/B`.",
+                            symbol)
+                }
+
+                InvalidVoicing { ref voicing } =>
+                {
+                    format!("Invalid voicing `{}`. Expected `close`, `open`, `drop2` or `root`.",
+                            voicing)
+                }
+
+                InvalidArpeggio { ref arpeggio } =>
+                {
+                    format!("Invalid arpeggio `{}`. Expected `up`, `down`, `updown` or `random`.",
+                            arpeggio)
+                }
+
+                InvalidArpRate { rate } =>
+                {
+                    format!("Invalid arpeggio rate `{}`. The rate is the number of arpeggiated notes in a whole note, and must be between 1 and 255.",
+                            rate)
+                }
+
+                InvalidStrum { strum } =>
+                {
+                    format!("Invalid strum `{}`. The strum is the delay between each note of a chord, and must be between 0 and 65535.",
+                            strum)
+                }
+
+                InvalidStrumDirection { ref direction } =>
+                {
+                    format!("Invalid strum direction `{}`. Expected `down` or `up`.",
+                            direction)
+                }
+
+                InvalidPan { pan } =>
+                {
+                    format!("Invalid pan `{}`. Pan must be between -64 (left) and 63 (right).",
+                            pan)
+                }
+
+                InvalidBendRange { range } =>
+                {
+                    format!("Invalid bend range `{}`. The range is the number of semitones the pitch can be bent by, and must be between 1 and 24.",
+                            range)
+                }
+
+                ArticulationInChord { ref note } =>
+                {
+                    format!("Unexpected articulation on note `{}` in a chord. Articulations apply to the whole chord, and are written after the closing `]`.",
+                            note)
+                }
+
+                OrnamentInChord { ref note } =>
+                {
+                    format!("Unexpected ornament on note `{}` in a chord. Only single notes can be ornamented.",
+                            note)
+                }
+
+                EmptyGraceNotes => "Grace notes must contain at least one note.".to_owned(),
+
+                DecoratedGraceNote { ref note } =>
+                {
+                    format!("Unexpected articulation or ornament on grace note `{}`.",
+                            note)
+                }
+
+                MisplacedGlissando => "A glissando must be written straight after a note without an ornament.".to_owned(),
+
+                InvalidPartModifiers { ref part } =>
+                {
+                    format!("Invalid modifiers on part `*{}`. Transpositions must be between -128 and 127 semitones, and augmentations must be greater than zero.",
+                            part)
+                }
+
+                InvalidKeySignature { ref key } =>
+                {
+                    format!("Invalid key signature `{}`. Expected a key like `D major` or `F# minor`.",
+                            key)
+                }
+
+                LateKeySignature =>
+                {
+                    "The `key:` of a piece must be set before any `play`, `part` or `section` blocks.".to_owned()
+                }
+
+                InvalidTempo { tempo } =>
+                {
+                    format!("Invalid tempo `{}`. Tempos must be at least 1 beat per minute.",
+                            tempo)
+                }
+
+                InvalidBeats { beats } =>
+                {
+                    format!("Invalid number of beats `{}`. Bars must have between 1 and 255 beats.",
+                            beats)
+                }
+
+                InvalidBeatUnit { unit } =>
+                {
+                    format!("Invalid meter `/{}`. The beat unit must be 1, 2, 4, 8, 16, 32 or 64.",
+                            unit)
+                }
+
+                InvalidVelocity { velocity } =>
+                {
+                    format!("Invalid velocity `{}`. Velocities must be between 1 and 127.",
+                            velocity)
+                }
+
+                InvalidBend { ref bend } =>
+                {
+                    format!("Invalid bend `{}`. Bends are measured in semitones, and must be between -24 and 24 with at most two decimal places.",
+                            bend)
+                }
+
+                InvalidController { controller } =>
+                {
+                    format!("Invalid controller `cc{}`. MIDI controllers are numbered from 0 to 127.",
+                            controller)
+                }
+
+                InvalidControllerValue { value } =>
+                {
+                    format!("Invalid controller value `{}`. Controller values must be between 0 and 127.",
+                            value)
+                }
+
+                InvalidPressure { pressure } =>
+                {
+                    format!("Invalid pressure `{}`. Pressure must be between 0 and 127.",
+                            pressure)
+                }
+
+                InvalidPressureNote { ref stave_prefix } =>
+                {
+                    format!("Invalid stave `{}:`. A pressure stave must be named after a note, such as `pressure_C#:`.",
+                            stave_prefix)
+                }
+
+                UnfinishedRamp { ref ramp } => {
+                    let value = match ramp.as_str() {
+                        "<" | ">" => "a dynamic marking",
+                        _ => "a number",
+                    };
+
+                    format!("There is no value for `{}` to change towards. It must be followed by {} later in the stave.",
+                            ramp,
+                            value)
+                }
+
                 _ => unreachable!(),
             };
 
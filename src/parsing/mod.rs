@@ -4,10 +4,12 @@ pub mod error;
 use self::data::*;
 use self::error::{ErrorType, ParsingError};
 
-use error::SourceMap;
+use error::{SourceLoc, SourceMap};
 use lexing::data::Token::*;
 use lexing::data::*;
-use notes::Midi;
+use notes::{
+    controller_number, dynamic_velocity, pressure_note, ChordSymbol, KeySignature, Midi, Voicing,
+};
 use std::borrow::Cow;
 use std::iter::Peekable;
 use std::slice::Iter;
@@ -133,11 +135,17 @@ fn parse_piece<'a>(stream: &mut TokenStream<'a>) -> Result<PieceNode<'a>, Parsin
 
 fn parse_piece_from_body<'a>(stream: &mut TokenStream<'a>) -> Result<PieceNode<'a>, ParsingError> {
     let mut voice_results = Vec::new();
+    let mut part_results = Vec::new();
     let mut play_results = Vec::new();
+    let mut section_results = Vec::new();
     let mut title = None;
     let mut composer = None;
     let mut beats = None;
+    let mut beat_unit = None;
     let mut tempo = None;
+    let mut key = None;
+    let mut arrangement = Vec::new();
+    let mut arrangement_locs = Vec::new();
 
     loop {
         let meta = *stream.peek().trust();
@@ -153,13 +161,27 @@ fn parse_piece_from_body<'a>(stream: &mut TokenStream<'a>) -> Result<PieceNode<'
                 }
                 voice_results.push(voice);
             }
+            Part => {
+                let part = parse_part(stream, key.unwrap_or_default());
+                if part.is_err() {
+                    poison_scope(stream, LeftBrace, RightBrace);
+                }
+                part_results.push(part);
+            }
             Play => {
-                let play = parse_play(stream);
+                let play = parse_play(stream, key.unwrap_or_default());
                 if play.is_err() {
                     poison_scope(stream, LeftBrace, RightBrace);
                 }
                 play_results.push(play);
             }
+            Section => {
+                let section = parse_section(stream, key.unwrap_or_default());
+                if section.is_err() {
+                    poison_scope(stream, LeftBrace, RightBrace);
+                }
+                section_results.push(section);
+            }
             _ => {
                 let attribute_key = parse_attribute_key(stream, "in `piece`")?;
                 match attribute_key {
@@ -167,8 +189,39 @@ fn parse_piece_from_body<'a>(stream: &mut TokenStream<'a>) -> Result<PieceNode<'
                     Key("composer") => {
                         composer = Some(try_parse_name(stream, "after `composer:`")?)
                     }
-                    Key("tempo") => tempo = Some(try_parse_num(stream, "after `tempo:`")? as u64),
-                    Key("beats") => beats = Some(try_parse_num(stream, "after `beats:`")? as u64),
+                    Key("tempo") => tempo = Some(try_parse_tempo(stream, "after `tempo:`")?),
+                    Key("beats") => beats = Some(try_parse_beats(stream, "after `beats:`")?),
+                    Key("meter") => {
+                        let (meter_beats, unit) = try_parse_meter(stream, "after `meter:`")?;
+                        beats = Some(meter_beats);
+                        beat_unit = Some(unit);
+                    }
+                    Key("key") => {
+                        if !play_results.is_empty()
+                            || !part_results.is_empty()
+                            || !section_results.is_empty()
+                        {
+                            return Err(ParsingError {
+                                loc: meta.loc.clone(),
+                                error: ErrorType::LateKeySignature,
+                            });
+                        }
+
+                        key = Some(try_parse_key(stream, "after `key:`")?);
+                    }
+                    Key("arrangement") => {
+                        let names_meta = *stream.peek().trust();
+                        let names = try_parse_name(stream, "after `arrangement:`")?;
+
+                        for (offset, name) in split_names(names) {
+                            arrangement.push(name);
+                            arrangement_locs.push(SourceLoc {
+                                col: names_meta.loc.col + offset,
+                                width: name.len(),
+                                ..names_meta.loc.clone()
+                            });
+                        }
+                    }
                     Key(key) | Ident(key) => {
                         return Err(ParsingError {
                             loc: meta.loc.clone(),
@@ -190,18 +243,40 @@ fn parse_piece_from_body<'a>(stream: &mut TokenStream<'a>) -> Result<PieceNode<'
     }
 
     let voices = error_swizzle(voice_results)?;
+    let parts = error_swizzle(part_results)?;
     let plays = error_swizzle(play_results)?;
+    let sections = error_swizzle(section_results)?;
 
     Ok(PieceNode {
         title,
         composer,
         beats,
+        beat_unit,
         tempo,
+        key,
+        arrangement,
+        arrangement_locs,
         voices,
+        parts,
         plays,
+        sections,
     })
 }
 
+/// Split a list of names separated by whitespace, along with the offset of each name.
+fn split_names(names: &str) -> Vec<(usize, &str)> {
+    let mut split = Vec::new();
+    let mut offset = 0;
+
+    for name in names.split_whitespace() {
+        let start = offset + names[offset..].find(name).trust();
+        split.push((start, name));
+        offset = start + name.len();
+    }
+
+    split
+}
+
 fn parse_attribute_key<'a>(
     stream: &mut TokenStream<'a>,
     context: &'static str,
@@ -255,6 +330,391 @@ fn try_parse_num(stream: &mut TokenStream, context: &'static str) -> Result<i64,
     }
 }
 
+fn try_parse_tempo(stream: &mut TokenStream, context: &'static str) -> Result<u64, ParsingError> {
+    let loc = stream.peek().trust().loc.clone();
+    let tempo = try_parse_num(stream, context)?;
+
+    if tempo <= 0 {
+        return Err(ParsingError {
+            loc,
+            error: ErrorType::InvalidTempo { tempo },
+        });
+    }
+
+    Ok(tempo as u64)
+}
+
+fn try_parse_beats(stream: &mut TokenStream, context: &'static str) -> Result<u64, ParsingError> {
+    let loc = stream.peek().trust().loc.clone();
+    let beats = try_parse_num(stream, context)?;
+
+    if beats <= 0 || beats >= 256 {
+        return Err(ParsingError {
+            loc,
+            error: ErrorType::InvalidBeats { beats },
+        });
+    }
+
+    Ok(beats as u64)
+}
+
+fn try_parse_controller_value(
+    stream: &mut TokenStream,
+    context: &'static str,
+) -> Result<u8, ParsingError> {
+    let loc = stream.peek().trust().loc.clone();
+    let value = try_parse_num(stream, context)?;
+
+    if !(0..=127).contains(&value) {
+        return Err(ParsingError {
+            loc,
+            error: ErrorType::InvalidControllerValue { value },
+        });
+    }
+
+    Ok(value as u8)
+}
+
+fn try_parse_meter(
+    stream: &mut TokenStream,
+    context: &'static str,
+) -> Result<(u64, u64), ParsingError> {
+    let beats = try_parse_beats(stream, context)?;
+    expect_token(stream, Slash, context)?;
+
+    let loc = stream.peek().trust().loc.clone();
+    let unit = try_parse_num(stream, context)?;
+
+    if unit <= 0 || unit > 64 || unit & (unit - 1) != 0 {
+        return Err(ParsingError {
+            loc,
+            error: ErrorType::InvalidBeatUnit { unit },
+        });
+    }
+
+    Ok((beats, unit as u64))
+}
+
+fn try_parse_key(
+    stream: &mut TokenStream,
+    context: &'static str,
+) -> Result<KeySignature, ParsingError> {
+    let meta = *stream.peek().trust();
+    let name = try_parse_name(stream, context)?;
+
+    KeySignature::from_name(name).ok_or_else(|| ParsingError {
+        loc: meta.loc.clone(),
+        error: ErrorType::InvalidKeySignature {
+            key: name.to_owned(),
+        },
+    })
+}
+
+fn starts_stave(token: Token) -> bool {
+    token == Barline || token == StartRepeat
+}
+
+/// Whether a stave with the given prefix contains numbers, rather than notes.
+fn is_value_stave(prefix: &str) -> bool {
+    prefix == "tempo"
+        || prefix == "vel"
+        || prefix == "dyn"
+        || prefix == "bend"
+        || prefix == "ped"
+        || prefix == "pressure"
+        || controller_number(prefix).is_some()
+        || pressure_note(prefix).is_some()
+}
+
+fn parse_ramp_kind(ramp: &str) -> RampKind {
+    match ramp {
+        "rit." => RampKind::Ritardando,
+        "accel." => RampKind::Accelerando,
+        "<" => RampKind::Crescendo,
+        ">" => RampKind::Diminuendo,
+        "/" => RampKind::Slide,
+        _ => unreachable!(),
+    }
+}
+
+/// Whether a gradual change such as `rit.` can be written in a stave with the given prefix.
+fn allows_ramp(prefix: &str, ramp: &str) -> bool {
+    match prefix {
+        "tempo" => ramp == "rit." || ramp == "accel.",
+        "dyn" => ramp == "<" || ramp == ">",
+        "bend" | "pressure" => ramp == "/",
+        _ => ramp == "/" && (controller_number(prefix).is_some() || pressure_note(prefix).is_some()),
+    }
+}
+
+fn validate_value(prefix: &str, value: i64) -> Result<(), ErrorType> {
+    match prefix {
+        "tempo" if value <= 0 => Err(ErrorType::InvalidTempo { tempo: value }),
+        "vel" | "dyn" if value <= 0 || value >= 128 => Err(ErrorType::InvalidVelocity { velocity: value }),
+        "ped" if !(0..=127).contains(&value) => Err(ErrorType::InvalidControllerValue { value }),
+        _ if (prefix == "pressure" || pressure_note(prefix).is_some())
+            && !(0..=127).contains(&value) =>
+        {
+            Err(ErrorType::InvalidPressure { pressure: value })
+        }
+        _ if controller_number(prefix).is_some() && !(0..=127).contains(&value) => {
+            Err(ErrorType::InvalidControllerValue { value })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The number of cents in a bend written in semitones, such as `-1.5`.
+fn parse_bend(text: &str) -> Option<i64> {
+    let (whole, fraction) = match text.find('.') {
+        Some(index) => (&text[..index], &text[(index + 1)..]),
+        None => (text, ""),
+    };
+
+    if fraction.len() > 2 {
+        return None;
+    }
+
+    let semitones = whole.parse::<i64>().ok()?;
+    let fraction = format!("{:0<2}", fraction).parse::<i64>().ok()?;
+    let cents = semitones.checked_abs()?.checked_mul(100)? + fraction;
+    let cents = if whole.starts_with('-') { -cents } else { cents };
+
+    if (-2400..=2400).contains(&cents) {
+        Some(cents)
+    } else {
+        None
+    }
+}
+
+/// The value or ramp written by a token in a stave with the given prefix, if it writes one.
+fn parse_value(meta: &MetaToken, stave_prefix: &str) -> Result<Option<NoteNode>, ParsingError> {
+    let value = match meta.token {
+        Ramp(ramp) if allows_ramp(stave_prefix, ramp) => {
+            return Ok(Some(NoteNode::Ramp {
+                length: 1,
+                kind: parse_ramp_kind(ramp),
+            }))
+        }
+        Bend(text) => parse_bend(text).ok_or_else(|| ParsingError {
+            loc: meta.loc.clone(),
+            error: ErrorType::InvalidBend {
+                bend: text.to_owned(),
+            },
+        })?,
+        Pedal(mark) => {
+            if mark == "v" {
+                127
+            } else {
+                0
+            }
+        }
+        Dynamic(marking) => i64::from(dynamic_velocity(marking).trust()),
+        Num(value) if is_value_stave(stave_prefix) => {
+            validate_value(stave_prefix, value).map_err(|error| ParsingError {
+                loc: meta.loc.clone(),
+                error,
+            })?;
+            value
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(NoteNode::Value { value, length: 1 }))
+}
+
+/// The most recent note in a stave, looking back through earlier bars if the current bar has none.
+fn previous_note(stave: &StaveNode, bar: &BarNode) -> Option<NoteNode> {
+    fn last_note(bar: &BarNode) -> Option<NoteNode> {
+        for note in bar.notes.iter().rev() {
+            match *note {
+                NoteNode::Note { .. }
+                | NoteNode::Chord { .. }
+                | NoteNode::ChordSymbol { .. }
+                | NoteNode::Value { .. } => return Some(note.clone()),
+                _ => (),
+            }
+        }
+
+        None
+    }
+
+    if let Some(note) = last_note(bar) {
+        return Some(note);
+    }
+
+    for bar_type in stave.bars.iter().rev() {
+        match *bar_type {
+            BarTypeNode::Bar(ref bar) => {
+                if let Some(note) = last_note(bar) {
+                    return Some(note);
+                }
+            }
+            BarTypeNode::RepeatBar => (),
+            BarTypeNode::PlayPart(_) => return None,
+        }
+    }
+
+    None
+}
+
+/// Check that each ramp, such as `rit.` or `<`, is followed by the value it changes towards.
+fn check_ramps_are_finished(stave: &StaveNode) -> Result<(), ParsingError> {
+    let mut ramp_loc = None;
+
+    for bar_type in &stave.bars {
+        if let BarTypeNode::Bar(ref bar) = *bar_type {
+            for (note, loc) in bar.notes.iter().zip(&bar.note_locs) {
+                match *note {
+                    NoteNode::Ramp { .. } => ramp_loc = Some(loc),
+                    NoteNode::Value { .. } => ramp_loc = None,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    match ramp_loc {
+        Some(loc) => Err(ParsingError {
+            loc: loc.clone(),
+            error: ErrorType::UnfinishedRamp {
+                ramp: loc.text().to_owned(),
+            },
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Separate a note from the articulation written after it, such as the `!` in `C!`.
+fn split_articulation(note: &str) -> (&str, Option<Articulation>) {
+    let articulation = match note.chars().last() {
+        Some('*') => Articulation::Staccato,
+        Some('~') => Articulation::Tenuto,
+        Some('!') => Articulation::Accent,
+        Some('^') => Articulation::Marcato,
+        _ => return (note, None),
+    };
+
+    (&note[..(note.len() - 1)], Some(articulation))
+}
+
+/// Separate a note from the ornament written after it, such as the `tr` in `C~tr`.
+fn split_ornament(note: &str) -> (&str, Option<&str>) {
+    match note.find('~') {
+        Some(index) => (&note[..index], Some(&note[(index + 1)..])),
+        None => (note, None),
+    }
+}
+
+/// The ornament with the given name, played around a note in the given key.
+fn parse_ornament(name: &str, midi: Midi, key: KeySignature) -> Ornament {
+    let upper = key.scale_step(midi, true);
+    let lower = key.scale_step(midi, false);
+
+    match name {
+        "tr" => Ornament::Trill { upper },
+        "mor" => Ornament::Mordent { lower },
+        "turn" => Ornament::Turn { upper, lower },
+        _ => unreachable!(),
+    }
+}
+
+/// Separate a chord from the strum direction written before it, such as the `v` in `v[CEG]`.
+fn split_strum(chord: &str) -> (&str, Option<StrumDirection>) {
+    let direction = match chord.chars().next() {
+        Some('v') => StrumDirection::Down,
+        Some('^') => StrumDirection::Up,
+        _ => return (chord, None),
+    };
+
+    (&chord[1..], Some(direction))
+}
+
+fn parse_strum_direction(name: &str) -> Option<StrumDirection> {
+    match name {
+        "down" => Some(StrumDirection::Down),
+        "up" => Some(StrumDirection::Up),
+        _ => None,
+    }
+}
+
+fn parse_arpeggio(name: &str) -> Option<Arpeggio> {
+    match name {
+        "up" => Some(Arpeggio::Up),
+        "down" => Some(Arpeggio::Down),
+        "updown" => Some(Arpeggio::UpDown),
+        "random" => Some(Arpeggio::Random),
+        _ => None,
+    }
+}
+
+fn parse_navigation(text: &str) -> Mark {
+    let until = if text.ends_with("fine") {
+        Until::Fine
+    } else if text.ends_with("coda") {
+        Until::Coda
+    } else {
+        Until::End
+    };
+
+    match text {
+        "segno" => Mark::Segno,
+        "coda" => Mark::Coda,
+        "fine" => Mark::Fine,
+        _ if text.starts_with("D.C.") => Mark::DaCapo { until },
+        _ => Mark::DalSegno { until },
+    }
+}
+
+fn parse_play_part<'a>(text: &'a str) -> Option<PlayPartNode<'a>> {
+    let name_end = text
+        .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+        .unwrap_or(text.len());
+    let name = &text[..name_end];
+    let mut modifiers = text[name_end..].chars().peekable();
+
+    let mut play_part = PlayPartNode {
+        name,
+        transpose: 0,
+        retrograde: false,
+        augmentation: 1,
+    };
+
+    while let Some(modifier) = modifiers.next() {
+        let mut digits = String::new();
+        while let Some(&ch) = modifiers.peek() {
+            if !ch.is_ascii_digit() {
+                break;
+            }
+            digits.push(ch);
+            modifiers.next();
+        }
+
+        match modifier {
+            '+' | '-' => {
+                let semitones = digits.parse::<i8>().ok()?;
+                let semitones = if modifier == '-' {
+                    -semitones
+                } else {
+                    semitones
+                };
+                play_part.transpose = play_part.transpose.checked_add(semitones)?;
+            }
+            '*' => {
+                let factor = digits.parse::<u32>().ok()?;
+                if factor == 0 {
+                    return None;
+                }
+                play_part.augmentation = play_part.augmentation.checked_mul(factor)?;
+            }
+            '<' => play_part.retrograde = !play_part.retrograde,
+            _ => return None,
+        }
+    }
+
+    Some(play_part)
+}
+
 fn parse_voice<'a>(stream: &mut TokenStream<'a>) -> Result<VoiceNode<'a>, ParsingError> {
     expect_token(stream, Voice, "in `piece`")?;
 
@@ -263,6 +723,19 @@ fn parse_voice<'a>(stream: &mut TokenStream<'a>) -> Result<VoiceNode<'a>, Parsin
     let mut program = None;
     let mut transpose = None;
     let mut volume = None;
+    let mut voicing = None;
+    let mut chord_octave = None;
+    let mut arpeggio = None;
+    let mut arp_rate = None;
+    let mut arp_seed = None;
+    let mut strum = None;
+    let mut strum_direction = None;
+    let mut bend_range = None;
+    let mut pan = None;
+    let mut reverb = None;
+    let mut chorus = None;
+    let mut expression = None;
+    let mut portamento = None;
 
     expect_token(stream, LeftBrace, "at `voice`")?;
 
@@ -298,6 +771,99 @@ fn parse_voice<'a>(stream: &mut TokenStream<'a>) -> Result<VoiceNode<'a>, Parsin
                 }
             }
             Key("volume") => volume = Some(try_parse_num(stream, "after `volume:`")? as u8),
+            Key("voicing") => {
+                let value_loc = &stream.peek().trust().loc;
+                let name = try_parse_name(stream, "after `voicing:`")?;
+                voicing = Some(Voicing::from_name(name).ok_or_else(|| ParsingError {
+                    loc: value_loc.clone(),
+                    error: ErrorType::InvalidVoicing {
+                        voicing: name.to_owned(),
+                    },
+                })?);
+            }
+            Key("chord_octave") => {
+                let octave = try_parse_num(stream, "after `chord_octave:`")?;
+                if !(-10..=10).contains(&octave) {
+                    return Err(ParsingError {
+                        loc: meta.loc.clone(),
+                        error: ErrorType::InvalidOctave { octave },
+                    });
+                }
+                chord_octave = Some(octave as i8);
+            }
+            Key("arpeggio") => {
+                let value_loc = &stream.peek().trust().loc;
+                let name = try_parse_name(stream, "after `arpeggio:`")?;
+                arpeggio = Some(parse_arpeggio(name).ok_or_else(|| ParsingError {
+                    loc: value_loc.clone(),
+                    error: ErrorType::InvalidArpeggio {
+                        arpeggio: name.to_owned(),
+                    },
+                })?);
+            }
+            Key("arp_rate") => {
+                let rate = try_parse_num(stream, "after `arp_rate:`")?;
+                if rate <= 0 || rate >= 256 {
+                    return Err(ParsingError {
+                        loc: meta.loc.clone(),
+                        error: ErrorType::InvalidArpRate { rate },
+                    });
+                }
+                arp_rate = Some(rate as u32);
+            }
+            Key("arp_seed") => arp_seed = Some(try_parse_num(stream, "after `arp_seed:`")? as u64),
+            Key("strum") => {
+                let offset = try_parse_num(stream, "after `strum:`")?;
+                if !(0..=65535).contains(&offset) {
+                    return Err(ParsingError {
+                        loc: meta.loc.clone(),
+                        error: ErrorType::InvalidStrum { strum: offset },
+                    });
+                }
+                strum = Some(if skip_token(stream, Ident("ms")) {
+                    Strum::Milliseconds(offset as u32)
+                } else {
+                    Strum::Ticks(offset as u32)
+                });
+            }
+            Key("strum_direction") => {
+                let value_loc = &stream.peek().trust().loc;
+                let name = try_parse_name(stream, "after `strum_direction:`")?;
+                strum_direction = Some(parse_strum_direction(name).ok_or_else(|| ParsingError {
+                    loc: value_loc.clone(),
+                    error: ErrorType::InvalidStrumDirection {
+                        direction: name.to_owned(),
+                    },
+                })?);
+            }
+            Key("bend_range") => {
+                let range = try_parse_num(stream, "after `bend_range:`")?;
+                if !(1..=24).contains(&range) {
+                    return Err(ParsingError {
+                        loc: meta.loc.clone(),
+                        error: ErrorType::InvalidBendRange { range },
+                    });
+                }
+                bend_range = Some(range as u8);
+            }
+            Key("pan") => {
+                let value = try_parse_num(stream, "after `pan:`")?;
+                if !(-64..=63).contains(&value) {
+                    return Err(ParsingError {
+                        loc: meta.loc.clone(),
+                        error: ErrorType::InvalidPan { pan: value },
+                    });
+                }
+                pan = Some(value as i8);
+            }
+            Key("reverb") => reverb = Some(try_parse_controller_value(stream, "after `reverb:`")?),
+            Key("chorus") => chorus = Some(try_parse_controller_value(stream, "after `chorus:`")?),
+            Key("expression") => {
+                expression = Some(try_parse_controller_value(stream, "after `expression:`")?)
+            }
+            Key("portamento") => {
+                portamento = Some(try_parse_controller_value(stream, "after `portamento:`")?)
+            }
             Key(key) | Ident(key) => {
                 return Err(ParsingError {
                     loc: meta.loc.clone(),
@@ -322,22 +888,137 @@ fn parse_voice<'a>(stream: &mut TokenStream<'a>) -> Result<VoiceNode<'a>, Parsin
         program,
         transpose,
         volume,
+        voicing,
+        chord_octave,
+        arpeggio,
+        arp_rate,
+        arp_seed,
+        strum,
+        strum_direction,
+        bend_range,
+        pan,
+        reverb,
+        chorus,
+        expression,
+        portamento,
     })
 }
 
-fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingError> {
+fn parse_play<'a>(
+    stream: &mut TokenStream<'a>,
+    key: KeySignature,
+) -> Result<PlayNode<'a>, ParsingError> {
     expect_token(stream, Play, "in `piece`")?;
 
     let error_loc = Some(stream.peek().trust().loc.clone());
 
     let voice = try_parse_name(stream, "in `play`").ok();
+
+    expect_token(stream, LeftBrace, "at `play`")?;
+
+    let play = parse_staves(stream, key, "in `play`", false)?;
+
+    Ok(PlayNode {
+        voice,
+        error_loc,
+        ..play
+    })
+}
+
+fn parse_part<'a>(
+    stream: &mut TokenStream<'a>,
+    key: KeySignature,
+) -> Result<PartNode<'a>, ParsingError> {
+    expect_token(stream, Part, "in `piece`")?;
+
+    let error_loc = Some(stream.peek().trust().loc.clone());
+
+    let name = try_parse_name(stream, "in `part`")?;
+
+    expect_token(stream, LeftBrace, "at `part`")?;
+
+    let staves = parse_staves(stream, key, "in `part`", true)?.staves;
+
+    Ok(PartNode {
+        name,
+        staves,
+        error_loc,
+    })
+}
+
+fn parse_section<'a>(
+    stream: &mut TokenStream<'a>,
+    key: KeySignature,
+) -> Result<SectionNode<'a>, ParsingError> {
+    expect_token(stream, Section, "in `piece`")?;
+
+    let error_loc = Some(stream.peek().trust().loc.clone());
+
+    let name = try_parse_name(stream, "in `section`")?;
+
+    expect_token(stream, LeftBrace, "at `section`")?;
+
+    let mut play_results = Vec::new();
+
+    loop {
+        let meta = *stream.peek().trust();
+        match meta.token {
+            RightBrace => {
+                stream.next();
+                break;
+            }
+            BlankLine => {
+                stream.next();
+            }
+            Play => {
+                let play = parse_play(stream, key);
+                if play.is_err() {
+                    poison_scope(stream, LeftBrace, RightBrace);
+                }
+                play_results.push(play);
+            }
+            EOF => {
+                return Err(ParsingError::eof(
+                    meta,
+                    "in `section`",
+                    "a `play` block".to_owned(),
+                ))
+            }
+            _ => {
+                return Err(ParsingError::unexpected(
+                    meta,
+                    "in `section`",
+                    "a `play` block".to_owned(),
+                ))
+            }
+        }
+    }
+
+    let plays = error_swizzle(play_results)?;
+
+    Ok(SectionNode {
+        name,
+        plays,
+        error_loc,
+    })
+}
+
+/// Parse the staves of a `play` or `part` block, along with the attributes changed between them.
+fn parse_staves<'a>(
+    stream: &mut TokenStream<'a>,
+    key: KeySignature,
+    context: &'static str,
+    in_part: bool,
+) -> Result<PlayNode<'a>, ParsingError> {
     let mut staves: Vec<StaveNode> = Vec::new();
+    let mut key = key;
+    let mut key_changes = Vec::new();
+    let mut tempo_changes = Vec::new();
+    let mut meter_changes = Vec::new();
 
     let mut anonymous_stave_count = 0;
     let mut allow_new_staves = true;
 
-    expect_token(stream, LeftBrace, "at `play`")?;
-
     loop {
         if skip_token(stream, RightBrace) {
             break;
@@ -349,7 +1030,7 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
             EOF => {
                 return Err(ParsingError::eof(
                     meta,
-                    "in `play`",
+                    context,
                     "a stave prefix".to_owned(),
                 ))
             }
@@ -360,11 +1041,57 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                     anonymous_stave_count = 0;
                 }
             }
+            // Parts are played at any point of any stave, so can't change the whole piece. Tempo
+            // staves would change it too.
+            Key(attribute)
+                if in_part
+                    && (attribute == "tempo"
+                        || (["key", "beats"].contains(&attribute)
+                            && !starts_stave(stream.peek().trust().token))) =>
+            {
+                return Err(ParsingError {
+                    loc: meta.loc.clone(),
+                    error: ErrorType::InvalidAttribute {
+                        attribute: attribute.to_owned(),
+                        structure: "part",
+                    },
+                });
+            }
+            Key("key") if !starts_stave(stream.peek().trust().token) => {
+                key = try_parse_key(stream, "after `key:`")?;
+                let bar = staves.iter().map(|stave| stave.bars.len()).max();
+                key_changes.push(KeyChangeNode {
+                    bar: bar.unwrap_or(0),
+                    key,
+                });
+                skip_token(stream, Comma);
+            }
+            Key("tempo") if !starts_stave(stream.peek().trust().token) => {
+                let tempo = try_parse_tempo(stream, "after `tempo:`")?;
+                let bar = staves.iter().map(|stave| stave.bars.len()).max();
+                tempo_changes.push(TempoChangeNode {
+                    bar: bar.unwrap_or(0),
+                    tempo,
+                });
+                skip_token(stream, Comma);
+            }
+            Key("beats") if !starts_stave(stream.peek().trust().token) => {
+                let beats = try_parse_beats(stream, "after `beats:`")?;
+                let bar = staves.iter().map(|stave| stave.bars.len()).max();
+                meter_changes.push(MeterChangeNode {
+                    bar: bar.unwrap_or(0),
+                    beats,
+                });
+                skip_token(stream, Comma);
+            }
             Key(raw_prefix) => {
                 let mut bar_loc = &stream.peek().trust().loc;
                 let mut next_bar_loc = bar_loc;
 
-                expect_token(stream, Barline, "after stave prefix")?;
+                // A `|:` is left in the stream, to be read as the start of a repeat.
+                if stream.peek().trust().token != StartRepeat {
+                    expect_token(stream, Barline, "after stave prefix")?;
+                }
 
                 let prefix = match raw_prefix {
                     "" => {
@@ -372,6 +1099,18 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                         anonymous_stave_count += 1;
                         Cow::Owned(anonymous_prefix)
                     }
+                    // The note of a pressure stave is read in the current key, like the notes it
+                    // presses, and written out in full so that it no longer depends on the key.
+                    _ if raw_prefix.starts_with("pressure_") => {
+                        let note = &raw_prefix["pressure_".len()..];
+                        let midi = Midi::from_note_in_key(note, key).ok_or_else(|| ParsingError {
+                            loc: meta.loc.clone(),
+                            error: ErrorType::InvalidPressureNote {
+                                stave_prefix: raw_prefix.to_owned(),
+                            },
+                        })?;
+                        Cow::Owned(format!("pressure_{}", midi.to_sharp()))
+                    }
                     prefix => Cow::Borrowed(prefix),
                 };
 
@@ -388,8 +1127,7 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                         if allow_new_staves {
                             staves.push(StaveNode {
                                 prefix,
-                                bars: Vec::new(),
-                                bar_locs: Vec::new(),
+                                ..Default::default()
                             });
                         } else {
                             return Err(ParsingError {
@@ -404,15 +1142,38 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                     &mut staves[stave_index]
                 };
 
+                if let Some(controller) = controller_number(raw_prefix) {
+                    if controller > 127 {
+                        return Err(ParsingError {
+                            loc: meta.loc.clone(),
+                            error: ErrorType::InvalidController { controller },
+                        });
+                    }
+                }
+
                 let stave_note = Midi::from_note(raw_prefix);
+                let value_stave = is_value_stave(raw_prefix);
                 let mut bar = BarNode::default();
                 let mut bar_is_repeat = false;
+                let mut bar_parts = Vec::new();
+                let mut open_tuplet = None;
+                let mut open_chord: Option<(Vec<Midi>, SourceLoc, Option<StrumDirection>)> = None;
+                let mut open_grace: Option<(Vec<Midi>, SourceLoc)> = None;
+                let mut grace_notes = Vec::new();
+                let mut glissando: Option<(usize, usize, Midi)> = None;
 
                 loop {
                     let mut bar_full = false;
                     let mut stave_full = false;
+                    let mut mark = None;
 
                     let meta = *stream.peek().trust();
+                    let grace_notes_before = !grace_notes.is_empty();
+                    let mut value = if open_chord.is_none() && open_grace.is_none() {
+                        parse_value(meta, raw_prefix)?
+                    } else {
+                        None
+                    };
 
                     match meta.token {
                         EOF => {
@@ -422,10 +1183,134 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                                 "stave contents".to_owned(),
                             ))
                         }
-                        Rest => {
+                        Note(text) if open_chord.is_some() => {
+                            let (note, articulation) = split_articulation(text);
+                            if articulation.is_some() {
+                                return Err(ParsingError {
+                                    loc: meta.loc.clone(),
+                                    error: ErrorType::ArticulationInChord {
+                                        note: text.to_owned(),
+                                    },
+                                });
+                            }
+
+                            let (note, ornament) = split_ornament(note);
+                            if ornament.is_some() {
+                                return Err(ParsingError {
+                                    loc: meta.loc.clone(),
+                                    error: ErrorType::OrnamentInChord {
+                                        note: text.to_owned(),
+                                    },
+                                });
+                            }
+
+                            let midi = Midi::from_note_in_key(note, key).ok_or_else(|| ParsingError {
+                                loc: meta.loc.clone(),
+                                error: ErrorType::InvalidNote {
+                                    note: note.to_owned(),
+                                },
+                            })?;
+                            open_chord.as_mut().trust().0.push(midi);
+                        }
+                        EndChord(text) if open_chord.is_some() => {
+                            let (midis, chord_loc, strum) = open_chord.take().trust();
+                            if midis.is_empty() {
+                                return Err(ParsingError {
+                                    loc: chord_loc,
+                                    error: ErrorType::EmptyChord,
+                                });
+                            }
+
+                            let (_, articulation) = split_articulation(text);
+                            bar.notes.push(NoteNode::Chord {
+                                midis,
+                                length: 1,
+                                articulation,
+                                strum,
+                            });
+                            bar.note_locs.push(chord_loc);
+                        }
+                        _ if open_chord.is_some() => {
+                            return Err(ParsingError::unexpected(
+                                meta,
+                                "in chord",
+                                "a note or `]`".to_owned(),
+                            ))
+                        }
+                        Note(text) if open_grace.is_some() => {
+                            let (note, articulation) = split_articulation(text);
+                            let (note, ornament) = split_ornament(note);
+                            if articulation.is_some() || ornament.is_some() {
+                                return Err(ParsingError {
+                                    loc: meta.loc.clone(),
+                                    error: ErrorType::DecoratedGraceNote {
+                                        note: text.to_owned(),
+                                    },
+                                });
+                            }
+
+                            let midi = Midi::from_note_in_key(note, key).ok_or_else(|| ParsingError {
+                                loc: meta.loc.clone(),
+                                error: ErrorType::InvalidNote {
+                                    note: note.to_owned(),
+                                },
+                            })?;
+                            open_grace.as_mut().trust().0.push(midi);
+                        }
+                        EndGrace if open_grace.is_some() => {
+                            let (midis, grace_loc) = open_grace.take().trust();
+                            if midis.is_empty() {
+                                return Err(ParsingError {
+                                    loc: grace_loc,
+                                    error: ErrorType::EmptyGraceNotes,
+                                });
+                            }
+
+                            grace_notes = midis;
+                        }
+                        _ if open_grace.is_some() => {
+                            return Err(ParsingError::unexpected(
+                                meta,
+                                "in grace notes",
+                                "a note or `}`".to_owned(),
+                            ))
+                        }
+                        // A glissando slides into the next note, so nothing else can come between.
+                        Rest | Hit | StartChord(_) | StartGrace | ChordName(_) | Ditto
+                        | RepeatBar | PlayPart(_) | Glissando | Key(_) | BlankLine
+                        | RightBrace
+                            if glissando.is_some() =>
+                        {
+                            return Err(ParsingError::unexpected(
+                                meta,
+                                "after glissando",
+                                "a note".to_owned(),
+                            ))
+                        }
+                        Rest => {
                             bar.notes.push(NoteNode::Rest { length: 1 });
                             bar.note_locs.push(meta.loc.clone());
                         }
+                        Hit | Note(_) | StartChord(_) | StartGrace if raw_prefix == "chords" => {
+                            return Err(ParsingError {
+                                loc: meta.loc.clone(),
+                                error: ErrorType::InvalidChordSymbol {
+                                    symbol: meta.span.1.to_owned(),
+                                },
+                            })
+                        }
+                        Hit | Note(_) | StartChord(_) | StartGrace if value_stave => {
+                            return Err(ParsingError {
+                                loc: meta.loc.clone(),
+                                error: ErrorType::NoteInValueStave {
+                                    stave_prefix: raw_prefix.to_owned(),
+                                },
+                            })
+                        }
+                        _ if value.is_some() => {
+                            bar.notes.push(value.take().trust());
+                            bar.note_locs.push(meta.loc.clone());
+                        }
                         Hit => {
                             let midi = stave_note.ok_or_else(|| ParsingError {
                                 loc: meta.loc.clone(),
@@ -433,23 +1318,119 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                                     stave_prefix: raw_prefix.to_owned(),
                                 },
                             })?;
-                            bar.notes.push(NoteNode::Note { midi, length: 1 });
+                            bar.notes.push(NoteNode::Note {
+                                midi,
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            });
                             bar.note_locs.push(meta.loc.clone());
                         }
+                        StartChord(text) => {
+                            let (_, strum) = split_strum(text);
+                            open_chord = Some((Vec::new(), meta.loc.clone(), strum));
+                        }
+                        StartGrace => open_grace = Some((Vec::new(), meta.loc.clone())),
                         Note(note) => {
-                            let midi = Midi::from_note(note).ok_or_else(|| ParsingError {
+                            let (note, articulation) = split_articulation(note);
+                            let (note, ornament) = split_ornament(note);
+                            let midi = Midi::from_note_in_key(note, key).ok_or_else(|| ParsingError {
                                 loc: meta.loc.clone(),
                                 error: ErrorType::InvalidNote {
                                     note: note.to_owned(),
                                 },
                             })?;
-                            bar.notes.push(NoteNode::Note { midi, length: 1 });
+                            let ornament = ornament.map(|name| parse_ornament(name, midi, key));
+
+                            if let Some((bar_index, note_index, from)) = glissando.take() {
+                                let notes = match stave.bars.get_mut(bar_index) {
+                                    Some(&mut BarTypeNode::Bar(ref mut earlier_bar)) => {
+                                        &mut earlier_bar.notes
+                                    }
+                                    _ => &mut bar.notes,
+                                };
+
+                                if let NoteNode::Note {
+                                    ornament: ref mut glissando_ornament,
+                                    ..
+                                } = notes[note_index]
+                                {
+                                    *glissando_ornament = Some(Ornament::Glissando {
+                                        semitones: midi.midi() - from.midi(),
+                                    });
+                                }
+                            }
+
+                            bar.notes.push(NoteNode::Note {
+                                midi,
+                                length: 1,
+                                articulation,
+                                ornament,
+                                grace_notes: ::std::mem::take(&mut grace_notes),
+                            });
                             bar.note_locs.push(meta.loc.clone());
                         }
+                        Glissando => {
+                            let midi = match bar.notes.last() {
+                                Some(&NoteNode::Note {
+                                    midi,
+                                    ornament: None,
+                                    ..
+                                }) => midi,
+                                _ => {
+                                    return Err(ParsingError {
+                                        loc: meta.loc.clone(),
+                                        error: ErrorType::MisplacedGlissando,
+                                    })
+                                }
+                            };
+
+                            // The note is found again once the bar it is in may have finished.
+                            glissando = Some((stave.bars.len(), bar.notes.len() - 1, midi));
+                        }
                         ExtendNote => {
                             bar.notes.push(NoteNode::Extension { length: 1 });
                             bar.note_locs.push(meta.loc.clone());
                         }
+                        ChordName(text) => {
+                            let (name, strum) = split_strum(text);
+                            let symbol = ChordSymbol::from_symbol(name).ok_or_else(|| ParsingError {
+                                loc: meta.loc.clone(),
+                                error: ErrorType::InvalidChordSymbol {
+                                    symbol: text.to_owned(),
+                                },
+                            })?;
+                            bar.notes.push(NoteNode::ChordSymbol {
+                                symbol,
+                                length: 1,
+                                strum,
+                            });
+                            bar.note_locs.push(meta.loc.clone());
+                        }
+                        Ditto => {
+                            let note = previous_note(stave, &bar).ok_or_else(|| ParsingError {
+                                loc: meta.loc.clone(),
+                                error: ErrorType::NothingToDitto,
+                            })?;
+                            bar.notes.push(note);
+                            bar.note_locs.push(meta.loc.clone());
+                        }
+                        // A length straight after a tuplet is the length of the whole tuplet.
+                        Num(num)
+                            if open_tuplet.is_none()
+                                && bar.tuplets.last().map(|tuplet| tuplet.end)
+                                    == Some(bar.notes.len()) =>
+                        {
+                            if num <= 0 || num >= 255 {
+                                return Err(ParsingError {
+                                    loc: meta.loc.clone(),
+                                    error: ErrorType::InvalidLength { length: num },
+                                });
+                            }
+
+                            bar.tuplets.last_mut().trust().length = num as u8;
+                        }
                         Num(num) => {
                             if num <= 0 || num >= 255 {
                                 return Err(ParsingError {
@@ -466,7 +1447,11 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                             match *previous_note {
                                 NoteNode::Rest { ref mut length }
                                 | NoteNode::Extension { ref mut length }
-                                | NoteNode::Note { ref mut length, .. } => *length = num as u8,
+                                | NoteNode::Note { ref mut length, .. }
+                                | NoteNode::Chord { ref mut length, .. }
+                                | NoteNode::ChordSymbol { ref mut length, .. }
+                                | NoteNode::Value { ref mut length, .. }
+                                | NoteNode::Ramp { ref mut length, .. } => *length = num as u8,
                             }
                         }
                         RepeatBar => {
@@ -480,10 +1465,92 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                             }
                             bar_is_repeat = true;
                         }
+                        PlayPart(text) => {
+                            let play_part = parse_play_part(text).ok_or_else(|| ParsingError {
+                                loc: meta.loc.clone(),
+                                error: ErrorType::InvalidPartModifiers {
+                                    part: text.to_owned(),
+                                },
+                            })?;
+                            bar_parts.push((play_part, &meta.loc));
+                        }
                         Barline => {
                             next_bar_loc = &meta.loc;
                             bar_full = true;
                         }
+                        StartRepeat => {
+                            next_bar_loc = &meta.loc;
+                            bar_full = true;
+                            mark = Some(Mark::StartRepeat);
+                        }
+                        EndRepeat(count) => {
+                            if count <= 0 || count >= 256 {
+                                return Err(ParsingError {
+                                    loc: meta.loc.clone(),
+                                    error: ErrorType::InvalidRepeatCount { count },
+                                });
+                            }
+
+                            next_bar_loc = &meta.loc;
+                            bar_full = true;
+                            mark = Some(Mark::EndRepeat {
+                                count: count as u32,
+                            });
+                        }
+                        Navigation(text) => {
+                            next_bar_loc = &meta.loc;
+                            bar_full = true;
+                            mark = Some(parse_navigation(text));
+                        }
+                        Volta(number) => {
+                            if number <= 0 || number >= 256 {
+                                return Err(ParsingError {
+                                    loc: meta.loc.clone(),
+                                    error: ErrorType::InvalidVoltaNumber { number },
+                                });
+                            }
+
+                            next_bar_loc = &meta.loc;
+                            bar_full = true;
+                            mark = Some(Mark::Volta {
+                                number: number as u32,
+                            });
+                        }
+                        StartTuplet(divisions) => {
+                            if divisions <= 0 || divisions >= 256 {
+                                return Err(ParsingError {
+                                    loc: meta.loc.clone(),
+                                    error: ErrorType::InvalidTuplet { divisions },
+                                });
+                            }
+
+                            if open_tuplet.is_some() {
+                                return Err(ParsingError {
+                                    loc: meta.loc.clone(),
+                                    error: ErrorType::NestedTuplet,
+                                });
+                            }
+
+                            open_tuplet = Some((bar.notes.len(), divisions, &meta.loc));
+                        }
+                        EndTuplet if open_tuplet.is_some() => {
+                            let (start, divisions, tuplet_loc) = open_tuplet.take().trust();
+                            let length: u32 =
+                                bar.notes[start..].iter().map(|note| note.length()).sum();
+
+                            if i64::from(length) != divisions {
+                                return Err(ParsingError {
+                                    loc: tuplet_loc.clone(),
+                                    error: ErrorType::MismatchedTuplet { divisions, length },
+                                });
+                            }
+
+                            bar.tuplets.push(TupletNode {
+                                start,
+                                end: bar.notes.len(),
+                                length: 1,
+                            });
+                        }
                         Key(_) | BlankLine | RightBrace => stave_full = true,
                         _ => {
                             return Err(ParsingError::unexpected(
@@ -494,10 +1561,38 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                         }
                     }
 
+                    // Grace notes must be followed straight away by the note they lead into.
+                    if grace_notes_before && !grace_notes.is_empty() {
+                        return Err(ParsingError::unexpected(
+                            meta,
+                            "after grace notes",
+                            "a note".to_owned(),
+                        ));
+                    }
+
                     if bar_full || stave_full {
+                        if let Some((_, _, tuplet_loc)) = open_tuplet {
+                            return Err(ParsingError {
+                                loc: tuplet_loc.clone(),
+                                error: ErrorType::UnfinishedTuplet,
+                            });
+                        }
+
                         let bar_is_nonempty = !bar.notes.is_empty();
 
-                        if bar_is_repeat || bar_is_nonempty {
+                        if !bar_parts.is_empty() {
+                            if bar_is_repeat || bar_is_nonempty {
+                                return Err(ParsingError {
+                                    loc: bar_loc.clone(),
+                                    error: ErrorType::ExcessNotesInPartBar,
+                                });
+                            }
+
+                            for (play_part, part_loc) in bar_parts.drain(..) {
+                                stave.bars.push(BarTypeNode::PlayPart(play_part));
+                                stave.bar_locs.push(part_loc.clone());
+                            }
+                        } else if bar_is_repeat || bar_is_nonempty {
                             if bar_is_repeat {
                                 if bar_is_nonempty {
                                     return Err(ParsingError {
@@ -519,6 +1614,14 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
                         }
                     }
 
+                    if let Some(mark) = mark {
+                        stave.marks.push(MarkNode {
+                            bar: stave.bars.len(),
+                            mark,
+                        });
+                        stave.mark_locs.push(meta.loc.clone());
+                    }
+
                     if stave_full {
                         break;
                     }
@@ -530,17 +1633,23 @@ fn parse_play<'a>(stream: &mut TokenStream<'a>) -> Result<PlayNode<'a>, ParsingE
             _ => {
                 return Err(ParsingError::unexpected(
                     meta,
-                    "in `play`",
+                    context,
                     "a stave prefix".to_owned(),
                 ))
             }
         }
     }
 
+    for stave in &staves {
+        check_ramps_are_finished(stave)?;
+    }
+
     Ok(PlayNode {
-        voice,
         staves,
-        error_loc,
+        key_changes,
+        tempo_changes,
+        meter_changes,
+        ..Default::default()
     })
 }
 
@@ -551,20 +1660,40 @@ mod tests {
 
     // TODO(***realname***): Clearly need a better way of carriaging errors
     fn doctor(parse_tree: &mut ParseTree) {
+        fn doctor_staves(staves: &mut [StaveNode]) {
+            for stave in staves {
+                for bar in &mut stave.bars {
+                    if let &mut BarTypeNode::Bar(ref mut bar) = bar {
+                        bar.note_locs.clear();
+                    }
+                }
+
+                stave.bar_locs.clear();
+                stave.mark_locs.clear();
+            }
+        }
+
         for piece in &mut parse_tree.pieces {
+            for part in &mut piece.parts {
+                part.error_loc = None;
+                doctor_staves(&mut part.staves);
+            }
+
             for play in &mut piece.plays {
                 play.error_loc = None;
+                doctor_staves(&mut play.staves);
+            }
 
-                for stave in &mut play.staves {
-                    for bar in &mut stave.bars {
-                        if let &mut BarTypeNode::Bar(ref mut bar) = bar {
-                            bar.note_locs.clear();
-                        }
-                    }
+            for section in &mut piece.sections {
+                section.error_loc = None;
 
-                    stave.bar_locs.clear();
+                for play in &mut section.plays {
+                    play.error_loc = None;
+                    doctor_staves(&mut play.staves);
                 }
             }
+
+            piece.arrangement_locs.clear();
         }
     }
 
@@ -660,6 +1789,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_meter() {
+        parsetest(
+            "meter: 6/8",
+            PieceNode {
+                beats: Some(6),
+                beat_unit: Some(8),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_meter() {
+        parsefailtest("meter: 6");
+        parsefailtest("meter: 6/6");
+        parsefailtest("meter: 0/4");
+        parsefailtest("meter: 3/128");
+    }
+
     #[test]
     fn fail_to_parse_invalid_attributes() {
         parsefailtest("titel: Title,");
@@ -690,6 +1839,19 @@ mod tests {
                     program: Some(0),
                     transpose: Some(-24),
                     volume: Some(99),
+                    voicing: None,
+                    chord_octave: None,
+                    arpeggio: None,
+                    arp_rate: None,
+                    arp_seed: None,
+                    strum: None,
+                    strum_direction: None,
+                    bend_range: None,
+                    pan: None,
+                    reverb: None,
+                    chorus: None,
+                    expression: None,
+                    portamento: None,
                 }],
                 ..Default::default()
             },
@@ -796,6 +1958,9 @@ mod tests {
                         vec![vec![NoteNode::Note {
                             midi: midi(60),
                             length: 1,
+                            articulation: None,
+                            ornament: None,
+                            grace_notes: Vec::new(),
                         }]],
                     )],
                     ..Default::default()
@@ -817,10 +1982,16 @@ mod tests {
                             NoteNode::Note {
                                 midi: midi(60),
                                 length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
                             },
                             NoteNode::Note {
                                 midi: midi(62),
                                 length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
                             },
                         ]],
                     )],
@@ -843,10 +2014,16 @@ mod tests {
                             vec![NoteNode::Note {
                                 midi: midi(60),
                                 length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
                             }],
                             vec![NoteNode::Note {
                                 midi: midi(60),
                                 length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
                             }],
                         ],
                     )],
@@ -869,6 +2046,9 @@ mod tests {
                             vec![vec![NoteNode::Note {
                                 midi: midi(60),
                                 length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
                             }]],
                         ),
                         stave(
@@ -876,6 +2056,9 @@ mod tests {
                             vec![vec![NoteNode::Note {
                                 midi: midi(67),
                                 length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
                             }]],
                         ),
                     ],
@@ -899,10 +2082,16 @@ mod tests {
                                 vec![NoteNode::Note {
                                     midi: midi(60),
                                     length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
                                 }],
                                 vec![NoteNode::Note {
                                     midi: midi(67),
                                     length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
                                 }],
                             ],
                         ),
@@ -912,10 +2101,16 @@ mod tests {
                                 vec![NoteNode::Note {
                                     midi: midi(67),
                                     length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
                                 }],
                                 vec![NoteNode::Note {
                                     midi: midi(74),
                                     length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
                                 }],
                             ],
                         ),
@@ -948,6 +2143,9 @@ mod tests {
                         vec![vec![NoteNode::Note {
                             midi: midi(60),
                             length: 4,
+                            articulation: None,
+                            ornament: None,
+                            grace_notes: Vec::new(),
                         }]],
                     )],
                     ..Default::default()
@@ -999,8 +2197,12 @@ mod tests {
                                 notes: vec![NoteNode::Note {
                                     length: 1,
                                     midi: Midi::from_raw(57).trust(),
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
                                 }],
                                 note_locs: Vec::new(),
+                                tuplets: Vec::new(),
                             }),
                             BarTypeNode::RepeatBar,
                         ],
@@ -1022,4 +2224,1477 @@ mod tests {
     fn fail_when_notes_after_repeat_sign() {
         parsefailtest("play { :| A | % A }");
     }
+
+    #[test]
+    fn parse_key_signature() {
+        parsetest(
+            "key: D major\nplay { :| F C F= }",
+            PieceNode {
+                key: KeySignature::from_name("D major"),
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![vec![
+                            NoteNode::Note {
+                                midi: midi(66),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            },
+                            NoteNode::Note {
+                                midi: midi(61),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            },
+                            NoteNode::Note {
+                                midi: midi(65),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn parse_key_change_in_play() {
+        parsetest(
+            "play { :| F | F |\n\nkey: E_ major\n\n:| E | }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![
+                            vec![NoteNode::Note {
+                                midi: midi(65),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                            vec![NoteNode::Note {
+                                midi: midi(65),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                            vec![NoteNode::Note {
+                                midi: midi(63),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                        ],
+                    )],
+                    key_changes: vec![KeyChangeNode {
+                        bar: 2,
+                        key: KeySignature::from_name("E_ major").unwrap(),
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_key_signature() {
+        parsefailtest("key: H major");
+    }
+
+    #[test]
+    fn fail_on_key_signature_after_play() {
+        parsefailtest("play {}\nkey: D major");
+    }
+
+    #[test]
+    fn parse_part() {
+        parsetest(
+            "part Riff { :| C }",
+            PieceNode {
+                parts: vec![PartNode {
+                    name: "Riff",
+                    staves: vec![stave(
+                        "V0",
+                        vec![vec![NoteNode::Note {
+                            midi: midi(60),
+                            length: 1,
+                            articulation: None,
+                            ornament: None,
+                            grace_notes: Vec::new(),
+                        }]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn parse_play_parts() {
+        parsetest(
+            "play { :| *Riff | *Riff-3<*2 }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![StaveNode {
+                        prefix: "V0".into(),
+                        bars: vec![
+                            BarTypeNode::PlayPart(PlayPartNode {
+                                name: "Riff",
+                                transpose: 0,
+                                retrograde: false,
+                                augmentation: 1,
+                            }),
+                            BarTypeNode::PlayPart(PlayPartNode {
+                                name: "Riff",
+                                transpose: -3,
+                                retrograde: true,
+                                augmentation: 2,
+                            }),
+                        ],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_when_notes_alongside_part() {
+        parsefailtest("play { :| C *Riff }");
+        parsefailtest("play { :| *Riff C }");
+    }
+
+    #[test]
+    fn fail_on_invalid_part_modifiers() {
+        parsefailtest("play { :| *Riff+200 }");
+        parsefailtest("play { :| *Riff*0 }");
+    }
+
+    #[test]
+    fn fail_on_attributes_in_part() {
+        parsefailtest("part Riff { :| F | F |\n\nkey: E_ major\n\n:| E | }");
+        parsefailtest("part Riff { :| C |\n\ntempo: 60\n\n:| C | }");
+        parsefailtest("part Riff { :| C |\n\nbeats: 3\n\n:| C | }");
+        parsefailtest("part Riff { tempo: | 60 |\n:| C | }");
+    }
+
+    #[test]
+    fn parse_section() {
+        parsetest(
+            "section Verse { play A { :| C } play B { :| D } }",
+            PieceNode {
+                sections: vec![SectionNode {
+                    name: "Verse",
+                    plays: vec![
+                        PlayNode {
+                            voice: Some("A"),
+                            staves: vec![stave(
+                                "V0",
+                                vec![vec![NoteNode::Note {
+                                    midi: midi(60),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }]],
+                            )],
+                            ..Default::default()
+                        },
+                        PlayNode {
+                            voice: Some("B"),
+                            staves: vec![stave(
+                                "V0",
+                                vec![vec![NoteNode::Note {
+                                    midi: midi(62),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }]],
+                            )],
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn parse_arrangement() {
+        parsetest(
+            "arrangement: Intro Verse  Chorus Verse",
+            PieceNode {
+                arrangement: vec!["Intro", "Verse", "Chorus", "Verse"],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn arrangement_locs_point_at_each_section() {
+        use lexing;
+
+        let source = "arrangement: Intro Verse  Chorus";
+        let (tokens, source_map) = lexing::lex(source, None).unwrap();
+        let result = parse(&tokens, &source_map).unwrap();
+
+        let locs = &result.pieces[0].arrangement_locs;
+        let texts: Vec<&str> = locs.iter().map(|loc| loc.text()).collect();
+        assert_eq!(texts, vec!["Intro", "Verse", "Chorus"]);
+    }
+
+    #[test]
+    fn fail_on_non_play_in_section() {
+        parsefailtest("section Verse { voice A {} }");
+        parsefailtest("section Verse { :| C }");
+        parsefailtest("section Verse { play A { :| C }");
+    }
+
+    #[test]
+    fn parse_ditto() {
+        parsetest(
+            "play { :| C2 \" - \" | \"3 }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![
+                            vec![
+                                NoteNode::Note {
+                                    midi: midi(60),
+                                    length: 2,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                },
+                                NoteNode::Note {
+                                    midi: midi(60),
+                                    length: 2,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                },
+                                NoteNode::Rest { length: 1 },
+                                NoteNode::Note {
+                                    midi: midi(60),
+                                    length: 2,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                },
+                            ],
+                            vec![NoteNode::Note {
+                                midi: midi(60),
+                                length: 3,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                        ],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn parse_ditto_in_drum_stave() {
+        parsetest(
+            "play { D: | x2 \" | % | \" }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![StaveNode {
+                        prefix: "D".into(),
+                        bars: vec![
+                            BarTypeNode::Bar(BarNode {
+                                notes: vec![
+                                    NoteNode::Note {
+                                        midi: midi(62),
+                                        length: 2,
+                                        articulation: None,
+                                        ornament: None,
+                                        grace_notes: Vec::new(),
+                                    },
+                                    NoteNode::Note {
+                                        midi: midi(62),
+                                        length: 2,
+                                        articulation: None,
+                                        ornament: None,
+                                        grace_notes: Vec::new(),
+                                    },
+                                ],
+                                note_locs: Vec::new(),
+                                tuplets: Vec::new(),
+                            }),
+                            BarTypeNode::RepeatBar,
+                            BarTypeNode::Bar(BarNode {
+                                notes: vec![NoteNode::Note {
+                                    midi: midi(62),
+                                    length: 2,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }],
+                                note_locs: Vec::new(),
+                                tuplets: Vec::new(),
+                            }),
+                        ],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_ditto_without_previous_note() {
+        parsefailtest("play { :| \" }");
+        parsefailtest("play { :| - \" }");
+        parsefailtest("play { :| *Riff | \" }");
+    }
+
+    #[test]
+    fn parse_repeat_marks() {
+        parsetest(
+            "play { :|: C :| D :|x4 }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![StaveNode {
+                        marks: vec![
+                            MarkNode {
+                                bar: 0,
+                                mark: Mark::StartRepeat,
+                            },
+                            MarkNode {
+                                bar: 1,
+                                mark: Mark::EndRepeat { count: 2 },
+                            },
+                            MarkNode {
+                                bar: 2,
+                                mark: Mark::EndRepeat { count: 4 },
+                            },
+                        ],
+                        ..stave(
+                            "V0",
+                            vec![
+                                vec![NoteNode::Note {
+                                    midi: midi(60),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }],
+                                vec![NoteNode::Note {
+                                    midi: midi(62),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }],
+                            ],
+                        )
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_repeat_count() {
+        parsefailtest("play { :| C :|x0 }");
+        parsefailtest("play { :| C :|x256 }");
+    }
+
+    #[test]
+    fn parse_voltas() {
+        parsetest(
+            "play { :| C [1 D :| [2 E }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![StaveNode {
+                        marks: vec![
+                            MarkNode {
+                                bar: 1,
+                                mark: Mark::Volta { number: 1 },
+                            },
+                            MarkNode {
+                                bar: 2,
+                                mark: Mark::EndRepeat { count: 2 },
+                            },
+                            MarkNode {
+                                bar: 2,
+                                mark: Mark::Volta { number: 2 },
+                            },
+                        ],
+                        ..stave(
+                            "V0",
+                            vec![
+                                vec![NoteNode::Note {
+                                    midi: midi(60),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }],
+                                vec![NoteNode::Note {
+                                    midi: midi(62),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }],
+                                vec![NoteNode::Note {
+                                    midi: midi(64),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }],
+                            ],
+                        )
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_volta_number() {
+        parsefailtest("play { :| C [0 D }");
+    }
+
+    #[test]
+    fn parse_navigation_marks() {
+        assert_eq!(parse_navigation("segno"), Mark::Segno);
+        assert_eq!(parse_navigation("coda"), Mark::Coda);
+        assert_eq!(parse_navigation("fine"), Mark::Fine);
+        assert_eq!(
+            parse_navigation("D.C."),
+            Mark::DaCapo { until: Until::End }
+        );
+        assert_eq!(
+            parse_navigation("D.C. al fine"),
+            Mark::DaCapo { until: Until::Fine }
+        );
+        assert_eq!(
+            parse_navigation("D.S. al  coda"),
+            Mark::DalSegno { until: Until::Coda }
+        );
+    }
+
+    #[test]
+    fn navigation_marks_end_bars() {
+        parsetest(
+            "play { :| segno C fine D }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![StaveNode {
+                        marks: vec![
+                            MarkNode {
+                                bar: 0,
+                                mark: Mark::Segno,
+                            },
+                            MarkNode {
+                                bar: 1,
+                                mark: Mark::Fine,
+                            },
+                        ],
+                        ..stave(
+                            "V0",
+                            vec![
+                                vec![NoteNode::Note {
+                                    midi: midi(60),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }],
+                                vec![NoteNode::Note {
+                                    midi: midi(62),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                }],
+                            ],
+                        )
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn parse_tempo_change_in_play() {
+        parsetest(
+            "play { :| C | C |\n\ntempo: 90\n\n:| C | }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![
+                            vec![NoteNode::Note {
+                                midi: midi(60),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                            vec![NoteNode::Note {
+                                midi: midi(60),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                            vec![NoteNode::Note {
+                                midi: midi(60),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                        ],
+                    )],
+                    tempo_changes: vec![TempoChangeNode { bar: 2, tempo: 90 }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn parse_tempo_stave() {
+        parsetest(
+            "play { tempo: | 120 - 90 . | 60 }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "tempo",
+                        vec![
+                            vec![
+                                NoteNode::Value {
+                                    value: 120,
+                                    length: 1,
+                                },
+                                NoteNode::Rest { length: 1 },
+                                NoteNode::Value {
+                                    value: 90,
+                                    length: 1,
+                                },
+                                NoteNode::Extension { length: 1 },
+                            ],
+                            vec![NoteNode::Value {
+                                value: 60,
+                                length: 1,
+                            }],
+                        ],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_notes_in_tempo_stave() {
+        parsefailtest("play { tempo: | 120 C }");
+        parsefailtest("play { tempo: | x }");
+    }
+
+    #[test]
+    fn fail_on_invalid_tempo() {
+        parsefailtest("tempo: 0");
+        parsefailtest("play { tempo: 0\n:| C }");
+        parsefailtest("play { tempo: | 0 }");
+    }
+
+    #[test]
+    fn parse_ramp_in_tempo_stave() {
+        parsetest(
+            "play { tempo: | rit. . | 60 }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "tempo",
+                        vec![
+                            vec![
+                                NoteNode::Ramp {
+                                    length: 1,
+                                    kind: RampKind::Ritardando,
+                                },
+                                NoteNode::Extension { length: 1 },
+                            ],
+                            vec![NoteNode::Value {
+                                value: 60,
+                                length: 1,
+                            }],
+                        ],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_unfinished_ramp() {
+        parsefailtest("play { tempo: | 120 rit. }");
+        parsefailtest("play { tempo: | 120 rit. | - }");
+        parsefailtest("play { dyn: | p < | }");
+    }
+
+    #[test]
+    fn fail_on_ramp_in_note_stave() {
+        parsefailtest("play { :| C rit. D }");
+    }
+
+    #[test]
+    fn parse_meter_change_in_play() {
+        parsetest(
+            "play { :| C |\n\nbeats: 3\n\n:| C }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![
+                            vec![NoteNode::Note {
+                                midi: midi(60),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                            vec![NoteNode::Note {
+                                midi: midi(60),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                        ],
+                    )],
+                    meter_changes: vec![MeterChangeNode { bar: 1, beats: 3 }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_beats() {
+        parsefailtest("beats: 0");
+        parsefailtest("play { beats: 300\n:| C }");
+    }
+
+    #[test]
+    fn parse_velocity_stave() {
+        parsetest(
+            "play { vel: | 100 - 60 }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "vel",
+                        vec![vec![
+                            NoteNode::Value {
+                                value: 100,
+                                length: 1,
+                            },
+                            NoteNode::Rest { length: 1 },
+                            NoteNode::Value {
+                                value: 60,
+                                length: 1,
+                            },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_velocity() {
+        parsefailtest("play { vel: | 0 }");
+        parsefailtest("play { vel: | 128 }");
+        parsefailtest("play { vel: | 100 C }");
+        parsefailtest("play { vel: | 100 rit. 60 }");
+    }
+
+    #[test]
+    fn parse_dynamics_stave() {
+        parsetest(
+            "play { dyn: | mf < - ff }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "dyn",
+                        vec![vec![
+                            NoteNode::Value {
+                                value: 80,
+                                length: 1,
+                            },
+                            NoteNode::Ramp {
+                                length: 1,
+                                kind: RampKind::Crescendo,
+                            },
+                            NoteNode::Rest { length: 1 },
+                            NoteNode::Value {
+                                value: 112,
+                                length: 1,
+                            },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_dynamics() {
+        parsefailtest("play { dyn: | mf C }");
+        parsefailtest("play { dyn: | mf rit. p }");
+        parsefailtest("play { dyn: | mf < }");
+    }
+
+    #[test]
+    fn parse_articulations() {
+        parsetest(
+            "play { :| C* D~ E! F^ }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![vec![
+                            NoteNode::Note {
+                                midi: midi(60),
+                                length: 1,
+                                articulation: Some(Articulation::Staccato),
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            },
+                            NoteNode::Note {
+                                midi: midi(62),
+                                length: 1,
+                                articulation: Some(Articulation::Tenuto),
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            },
+                            NoteNode::Note {
+                                midi: midi(64),
+                                length: 1,
+                                articulation: Some(Articulation::Accent),
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            },
+                            NoteNode::Note {
+                                midi: midi(65),
+                                length: 1,
+                                articulation: Some(Articulation::Marcato),
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn ditto_repeats_articulations() {
+        parsetest(
+            "play { :| C!2 \" }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![vec![
+                            NoteNode::Note {
+                                midi: midi(60),
+                                length: 2,
+                                articulation: Some(Articulation::Accent),
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            },
+                            NoteNode::Note {
+                                midi: midi(60),
+                                length: 2,
+                                articulation: Some(Articulation::Accent),
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn parse_tuplets() {
+        let note = |midi: i8| NoteNode::Note {
+            midi: Midi::from_raw(midi).trust(),
+            length: 1,
+            articulation: None,
+            ornament: None,
+            grace_notes: Vec::new(),
+        };
+
+        parsetest(
+            "play { :| C (3 D E F) (3 G A B)2 }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![StaveNode {
+                        prefix: "V0".into(),
+                        bars: vec![BarTypeNode::Bar(BarNode {
+                            notes: vec![
+                                note(60),
+                                note(62),
+                                note(64),
+                                note(65),
+                                note(67),
+                                note(57),
+                                note(59),
+                            ],
+                            note_locs: Vec::new(),
+                            tuplets: vec![
+                                TupletNode {
+                                    start: 1,
+                                    end: 4,
+                                    length: 1,
+                                },
+                                TupletNode {
+                                    start: 4,
+                                    end: 7,
+                                    length: 2,
+                                },
+                            ],
+                        })],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_tuplets() {
+        parsefailtest("play { :| (0 C) }");
+        parsefailtest("play { :| (3 C D) }");
+        parsefailtest("play { :| (3 C D E }");
+        parsefailtest("play { :| (3 C D E | F) }");
+        parsefailtest("play { :| (3 C (3 D E F) E) }");
+        parsefailtest("play { :| C) }");
+    }
+
+    #[test]
+    fn parse_chords() {
+        parsetest(
+            "play { :| [CEG] [C E_]2 [Ga]* \" }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![vec![
+                            NoteNode::Chord {
+                                midis: vec![midi(60), midi(64), midi(67)],
+                                length: 1,
+                                articulation: None,
+                                strum: None,
+                            },
+                            NoteNode::Chord {
+                                midis: vec![midi(60), midi(63)],
+                                length: 2,
+                                articulation: None,
+                                strum: None,
+                            },
+                            NoteNode::Chord {
+                                midis: vec![midi(67), midi(69)],
+                                length: 1,
+                                articulation: Some(Articulation::Staccato),
+                                strum: None,
+                            },
+                            NoteNode::Chord {
+                                midis: vec![midi(67), midi(69)],
+                                length: 1,
+                                articulation: Some(Articulation::Staccato),
+                                strum: None,
+                            },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_chords() {
+        parsefailtest("play { :| [] }");
+        parsefailtest("play { :| [C E* G] }");
+        parsefailtest("play { :| [C E G }");
+        parsefailtest("play { :| [C E | G] }");
+        parsefailtest("play { :| [C - G] }");
+        parsefailtest("play { :| [C [E] G] }");
+        parsefailtest("play { :| C] }");
+        parsefailtest("play { tempo: | [C] }");
+    }
+
+    #[test]
+    fn parse_chord_symbols() {
+        parsetest(
+            "voice A { voicing: drop2, chord_octave: -1 } play A { chords: | Cmaj7 G7/B 2 \" }",
+            PieceNode {
+                voices: vec![VoiceNode {
+                    name: "A",
+                    voicing: Some(Voicing::Drop2),
+                    chord_octave: Some(-1),
+                    ..Default::default()
+                }],
+                plays: vec![PlayNode {
+                    voice: Some("A"),
+                    staves: vec![stave(
+                        "chords",
+                        vec![vec![
+                            NoteNode::ChordSymbol {
+                                symbol: ChordSymbol::from_symbol("Cmaj7").unwrap(),
+                                length: 1,
+                                strum: None,
+                            },
+                            NoteNode::ChordSymbol {
+                                symbol: ChordSymbol::from_symbol("G7/B").unwrap(),
+                                length: 2,
+                                strum: None,
+                            },
+                            NoteNode::ChordSymbol {
+                                symbol: ChordSymbol::from_symbol("G7/B").unwrap(),
+                                length: 2,
+                                strum: None,
+                            },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_chord_symbols() {
+        parsefailtest("play { chords: | Cmaj8 }");
+        parsefailtest("play { chords: | Cm7b9/E }");
+        parsefailtest("play { chords: | C a }");
+        parsefailtest("play { chords: | C x }");
+        parsefailtest("voice A { voicing: wide }");
+        parsefailtest("voice A { chord_octave: 11 }");
+    }
+
+    #[test]
+    fn parse_arpeggio_attributes() {
+        parsetest(
+            "voice Harp { arpeggio: updown, arp_rate: 8, arp_seed: 42 }",
+            PieceNode {
+                voices: vec![VoiceNode {
+                    name: "Harp",
+                    arpeggio: Some(Arpeggio::UpDown),
+                    arp_rate: Some(8),
+                    arp_seed: Some(42),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_arpeggio_attributes() {
+        parsefailtest("voice Harp { arpeggio: sideways }");
+        parsefailtest("voice Harp { arp_rate: 0 }");
+        parsefailtest("voice Harp { arp_rate: 256 }");
+    }
+
+    #[test]
+    fn parse_strums() {
+        parsetest(
+            "voice Guitar { strum: 15ms, strum_direction: up } \
+             play Guitar { :| v[CEG] ^[CEG]\n chords: | ^Am7 }",
+            PieceNode {
+                voices: vec![VoiceNode {
+                    name: "Guitar",
+                    strum: Some(Strum::Milliseconds(15)),
+                    strum_direction: Some(StrumDirection::Up),
+                    ..Default::default()
+                }],
+                plays: vec![PlayNode {
+                    voice: Some("Guitar"),
+                    staves: vec![
+                        stave(
+                            "V0",
+                            vec![vec![
+                                NoteNode::Chord {
+                                    midis: vec![midi(60), midi(64), midi(67)],
+                                    length: 1,
+                                    articulation: None,
+                                    strum: Some(StrumDirection::Down),
+                                },
+                                NoteNode::Chord {
+                                    midis: vec![midi(60), midi(64), midi(67)],
+                                    length: 1,
+                                    articulation: None,
+                                    strum: Some(StrumDirection::Up),
+                                },
+                            ]],
+                        ),
+                        stave(
+                            "chords",
+                            vec![vec![NoteNode::ChordSymbol {
+                                symbol: ChordSymbol::from_symbol("Am7").unwrap(),
+                                length: 1,
+                                strum: Some(StrumDirection::Up),
+                            }]],
+                        ),
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+
+        parsetest(
+            "voice Guitar { strum: 20 }",
+            PieceNode {
+                voices: vec![VoiceNode {
+                    name: "Guitar",
+                    strum: Some(Strum::Ticks(20)),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_strums() {
+        parsefailtest("voice Guitar { strum: -1 }");
+        parsefailtest("voice Guitar { strum: 65536 }");
+        parsefailtest("voice Guitar { strum: 10 s }");
+        parsefailtest("voice Guitar { strum_direction: sideways }");
+        parsefailtest("play { :| v[] }");
+    }
+
+    #[test]
+    fn parse_ornaments() {
+        parsetest(
+            "key: D major\nplay { :| {d B}c C~tr2 E~mor! F~turn }",
+            PieceNode {
+                key: KeySignature::from_name("D major"),
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![vec![
+                            NoteNode::Note {
+                                midi: midi(73),
+                                length: 1,
+                                articulation: None,
+                                ornament: None,
+                                grace_notes: vec![midi(74), midi(59)],
+                            },
+                            NoteNode::Note {
+                                midi: midi(61),
+                                length: 2,
+                                articulation: None,
+                                ornament: Some(Ornament::Trill { upper: 1 }),
+                                grace_notes: Vec::new(),
+                            },
+                            NoteNode::Note {
+                                midi: midi(64),
+                                length: 1,
+                                articulation: Some(Articulation::Accent),
+                                ornament: Some(Ornament::Mordent { lower: -2 }),
+                                grace_notes: Vec::new(),
+                            },
+                            NoteNode::Note {
+                                midi: midi(66),
+                                length: 1,
+                                articulation: None,
+                                ornament: Some(Ornament::Turn {
+                                    upper: 1,
+                                    lower: -2,
+                                }),
+                                grace_notes: Vec::new(),
+                            },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_ornaments() {
+        parsefailtest("play { :| {}C }");
+        parsefailtest("play { :| {D*}C }");
+        parsefailtest("play { :| {D~tr}C }");
+        parsefailtest("play { :| {D}- }");
+        parsefailtest("play { :| {D} | C }");
+        parsefailtest("play { :| {D}[CE] }");
+        parsefailtest("play { :| {D C }");
+        parsefailtest("play { :| [C~tr E] }");
+        parsefailtest("play { C:| {x}x }");
+        parsefailtest("play { vel: | {D}C }");
+    }
+
+    #[test]
+    fn parse_bends() {
+        parsetest(
+            "voice Lead { bend_range: 12 } play Lead { bend: | 0 / +2 -0.5 - 12.25 }",
+            PieceNode {
+                voices: vec![VoiceNode {
+                    name: "Lead",
+                    bend_range: Some(12),
+                    ..Default::default()
+                }],
+                plays: vec![PlayNode {
+                    voice: Some("Lead"),
+                    staves: vec![stave(
+                        "bend",
+                        vec![vec![
+                            NoteNode::Value {
+                                value: 0,
+                                length: 1,
+                            },
+                            NoteNode::Ramp {
+                                length: 1,
+                                kind: RampKind::Slide,
+                            },
+                            NoteNode::Value {
+                                value: 200,
+                                length: 1,
+                            },
+                            NoteNode::Value {
+                                value: -50,
+                                length: 1,
+                            },
+                            NoteNode::Rest { length: 1 },
+                            NoteNode::Value {
+                                value: 1225,
+                                length: 1,
+                            },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_bends() {
+        parsefailtest("play { bend: | 25 }");
+        parsefailtest("play { bend: | -24.5 }");
+        parsefailtest("play { bend: | 0.125 }");
+        parsefailtest("play { bend: | 0 / }");
+        parsefailtest("play { bend: | C }");
+        parsefailtest("voice Lead { bend_range: 0 }");
+        parsefailtest("voice Lead { bend_range: 25 }");
+    }
+
+    #[test]
+    fn parse_glissandos() {
+        parsetest(
+            "play { :| C/ G2 | c/ | . | C,~ }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "V0",
+                        vec![
+                            vec![
+                                NoteNode::Note {
+                                    midi: midi(60),
+                                    length: 1,
+                                    articulation: None,
+                                    ornament: Some(Ornament::Glissando { semitones: 7 }),
+                                    grace_notes: Vec::new(),
+                                },
+                                NoteNode::Note {
+                                    midi: midi(67),
+                                    length: 2,
+                                    articulation: None,
+                                    ornament: None,
+                                    grace_notes: Vec::new(),
+                                },
+                            ],
+                            vec![NoteNode::Note {
+                                midi: midi(72),
+                                length: 1,
+                                articulation: None,
+                                ornament: Some(Ornament::Glissando { semitones: -24 }),
+                                grace_notes: Vec::new(),
+                            }],
+                            vec![NoteNode::Extension { length: 1 }],
+                            vec![NoteNode::Note {
+                                midi: midi(48),
+                                length: 1,
+                                articulation: Some(Articulation::Tenuto),
+                                ornament: None,
+                                grace_notes: Vec::new(),
+                            }],
+                        ],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_misplaced_glissandos() {
+        parsefailtest("play { :| /C }");
+        parsefailtest("play { :| - / C }");
+        parsefailtest("play { :| [CE]/ G }");
+        parsefailtest("play { :| C~tr/ G }");
+        parsefailtest("play { :| C/ - G }");
+        parsefailtest("play { :| C/ [EG] }");
+        parsefailtest("play { :| C/ / G }");
+        parsefailtest("play { :| C/ }");
+        parsefailtest("play { :| [C/ E] }");
+    }
+
+    #[test]
+    fn parse_controller_staves() {
+        parsetest(
+            "play { cc74: | 0 / 127 - }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "cc74",
+                        vec![vec![
+                            NoteNode::Value {
+                                value: 0,
+                                length: 1,
+                            },
+                            NoteNode::Ramp {
+                                length: 1,
+                                kind: RampKind::Slide,
+                            },
+                            NoteNode::Value {
+                                value: 127,
+                                length: 1,
+                            },
+                            NoteNode::Rest { length: 1 },
+                        ]],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_controller_staves() {
+        parsefailtest("play { cc128: | 0 }");
+        parsefailtest("play { cc7: | 128 }");
+        parsefailtest("play { cc7: | C }");
+        parsefailtest("play { cc7: | 0 rit. 100 }");
+        parsefailtest("play { cc7: | 0 / }");
+    }
+
+    #[test]
+    fn parse_pedal_stave() {
+        parsetest(
+            "play { ped: | v . ^ v | 40 }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![stave(
+                        "ped",
+                        vec![
+                            vec![
+                                NoteNode::Value {
+                                    value: 127,
+                                    length: 1,
+                                },
+                                NoteNode::Extension { length: 1 },
+                                NoteNode::Value {
+                                    value: 0,
+                                    length: 1,
+                                },
+                                NoteNode::Value {
+                                    value: 127,
+                                    length: 1,
+                                },
+                            ],
+                            vec![NoteNode::Value {
+                                value: 40,
+                                length: 1,
+                            }],
+                        ],
+                    )],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_pedal_marks() {
+        parsefailtest("play { ped: | v C }");
+        parsefailtest("play { ped: | v / ^ }");
+        parsefailtest("play { ped: | 128 }");
+    }
+
+    #[test]
+    fn parse_mix_attributes() {
+        parsetest(
+            "voice Pad { pan: -64, reverb: 80, chorus: 0, expression: 127, portamento: 20 }",
+            PieceNode {
+                voices: vec![VoiceNode {
+                    name: "Pad",
+                    pan: Some(-64),
+                    reverb: Some(80),
+                    chorus: Some(0),
+                    expression: Some(127),
+                    portamento: Some(20),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_mix_attributes() {
+        parsefailtest("voice Pad { pan: 64 }");
+        parsefailtest("voice Pad { pan: -65 }");
+        parsefailtest("voice Pad { reverb: 128 }");
+        parsefailtest("voice Pad { chorus: -1 }");
+        parsefailtest("voice Pad { expression: loud }");
+        parsefailtest("voice Pad { portamento: 200 }");
+    }
+
+    #[test]
+    fn parse_pressure_staves() {
+        parsetest(
+            "play { pressure: | / 100\npressure_E: | 0 - }",
+            PieceNode {
+                plays: vec![PlayNode {
+                    staves: vec![
+                        stave(
+                            "pressure",
+                            vec![vec![
+                                NoteNode::Ramp {
+                                    length: 1,
+                                    kind: RampKind::Slide,
+                                },
+                                NoteNode::Value {
+                                    value: 100,
+                                    length: 1,
+                                },
+                            ]],
+                        ),
+                        stave(
+                            "pressure_E",
+                            vec![vec![
+                                NoteNode::Value {
+                                    value: 0,
+                                    length: 1,
+                                },
+                                NoteNode::Rest { length: 1 },
+                            ]],
+                        ),
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn pressure_notes_are_read_in_the_key() {
+        parsetest(
+            "key: F major\nplay { pressure_B: | 64\npressure_B=: | 32 }",
+            PieceNode {
+                key: KeySignature::from_name("F major"),
+                plays: vec![PlayNode {
+                    staves: vec![
+                        stave(
+                            "pressure_A#",
+                            vec![vec![NoteNode::Value {
+                                value: 64,
+                                length: 1,
+                            }]],
+                        ),
+                        stave(
+                            "pressure_B",
+                            vec![vec![NoteNode::Value {
+                                value: 32,
+                                length: 1,
+                            }]],
+                        ),
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_pressure_staves() {
+        parsefailtest("play { pressure: | 128 }");
+        parsefailtest("play { pressure_C: | 200 }");
+        parsefailtest("play { pressure: | C }");
+        parsefailtest("play { pressure_H: | 64 }");
+        parsefailtest("play { pressure_C: | 0 / }");
+    }
 }
@@ -1,5 +1,6 @@
 use error::SourceLoc;
-use notes::Midi;
+use notes::{round, KeySignature, Midi, Voicing};
+use parsing::data::{Arpeggio, Articulation, Mark, Ornament, Strum, StrumDirection};
 
 #[derive(Debug, PartialEq)]
 pub struct Piece<'a> {
@@ -7,10 +8,70 @@ pub struct Piece<'a> {
     pub composer: Option<&'a str>,
     pub tempo: u64,
     pub beats: u64,
+    pub beat_unit: u64,
+    pub key: KeySignature,
+    pub meter_changes: Vec<MeterChange>,
 
     pub voices: Vec<Voice<'a>>,
 }
 
+/// The number of the meter's notes which make up each beat that is felt. Compound meters such as
+/// 6/8 or 12/8 group them in threes, while 6/4 is treated as six quarter-note beats.
+fn notes_per_felt_beat(beats: u64, beat_unit: u64) -> u64 {
+    match beats % 3 {
+        0 if beats > 3 && beat_unit >= 8 => 3,
+        _ => 1,
+    }
+}
+
+impl<'a> Piece<'a> {
+    /// The length of the beat which the tempo counts, as a fraction of a whole note.
+    pub fn tempo_beat(&self) -> (u64, u64) {
+        (notes_per_felt_beat(self.beats, self.beat_unit), self.beat_unit)
+    }
+
+    /// The length of the beat felt in the given bar, as a fraction of a whole note.
+    pub fn felt_beat_in_bar(&self, bar: u32) -> (u64, u64) {
+        let beats = self.beats_in_bar(bar);
+        (notes_per_felt_beat(beats, self.beat_unit), self.beat_unit)
+    }
+
+    /// The number of beats felt in the given bar, e.g. two in a bar of 6/8.
+    pub fn felt_beats_in_bar(&self, bar: u32) -> u64 {
+        let beats = self.beats_in_bar(bar);
+        beats / notes_per_felt_beat(beats, self.beat_unit)
+    }
+
+    /// The number of beats in the given bar, after any changes of meter.
+    pub fn beats_in_bar(&self, bar: u32) -> u64 {
+        self.meter_changes
+            .iter()
+            .take_while(|meter_change| meter_change.bar <= bar)
+            .last()
+            .map(|meter_change| meter_change.beats)
+            .unwrap_or(self.beats)
+    }
+
+    /// The number of beats before the given bar begins.
+    pub fn beats_before_bar(&self, bar: u32) -> u64 {
+        let mut beats = 0;
+        let mut segment_start = 0;
+        let mut segment_beats = self.beats;
+
+        for meter_change in &self.meter_changes {
+            if meter_change.bar >= bar {
+                break;
+            }
+
+            beats += u64::from(meter_change.bar - segment_start) * segment_beats;
+            segment_start = meter_change.bar;
+            segment_beats = meter_change.beats;
+        }
+
+        beats + u64::from(bar - segment_start) * segment_beats
+    }
+}
+
 impl<'a> Default for Piece<'a> {
     fn default() -> Self {
         Piece {
@@ -18,6 +79,9 @@ impl<'a> Default for Piece<'a> {
             composer: None,
             tempo: 120,
             beats: 4,
+            beat_unit: 4,
+            key: KeySignature::default(),
+            meter_changes: Vec::new(),
             voices: Vec::new(),
         }
     }
@@ -30,11 +94,113 @@ pub struct Voice<'a> {
     pub program: u8,
     pub transpose: i8,
     pub volume: Option<f64>,
+    pub voicing: Voicing,
+    pub chord_octave: i8,
+    pub arpeggio: Option<Arpeggio>,
+    pub arp_rate: u32,
+    pub arp_seed: u64,
+    pub strum: Option<Strum>,
+    pub strum_direction: StrumDirection,
+    pub bend_range: u8,
+    pub pan: Option<i8>,
+    pub reverb: Option<u8>,
+    pub chorus: Option<u8>,
+    pub expression: Option<u8>,
+    pub portamento: Option<u8>,
     pub notes: Vec<Note>,
+    pub key_changes: Vec<KeyChange>,
+    pub tempo_changes: Vec<TempoChange>,
+    pub dynamic_changes: Vec<DynamicChange>,
+    pub bend_changes: Vec<BendChange>,
+    pub controller_changes: Vec<ControllerChange>,
+    pub pressure_changes: Vec<PressureChange>,
+    pub repeat_structure: Option<RepeatStructure>,
     pub divisions_per_bar: u32,
     pub debug_bar_info: Vec<DebugBarInfo>,
 }
 
+impl<'a> Voice<'a> {
+    /// The velocity given by the dynamics at a position, if any have been marked by then. Notes
+    /// during a hairpin are given velocities between the dynamics on either side of it.
+    pub fn dynamic_at(&self, position: u32) -> Option<u8> {
+        let mut velocity = None;
+
+        for dynamic_change in &self.dynamic_changes {
+            if dynamic_change.position <= position {
+                velocity = Some(dynamic_change.velocity);
+                continue;
+            }
+
+            if let Some(ramp_start) = dynamic_change.ramp_start {
+                if ramp_start <= position {
+                    let from = f64::from(velocity.unwrap_or(DEFAULT_DYNAMIC));
+                    let to = f64::from(dynamic_change.velocity);
+                    let progress = f64::from(position - ramp_start)
+                        / f64::from(dynamic_change.position - ramp_start);
+
+                    velocity = Some(round(from + (to - from) * progress) as u8);
+                }
+            }
+
+            break;
+        }
+
+        velocity
+    }
+
+    /// The controllers set at the start of the voice by its mix attributes, with their values.
+    pub fn mix_controllers(&self) -> Vec<(u8, u8)> {
+        let mut controllers = Vec::new();
+
+        if let Some(pan) = self.pan {
+            controllers.push((10, (i16::from(pan) + 64) as u8));
+        }
+        if let Some(reverb) = self.reverb {
+            controllers.push((91, reverb));
+        }
+        if let Some(chorus) = self.chorus {
+            controllers.push((93, chorus));
+        }
+        if let Some(expression) = self.expression {
+            controllers.push((11, expression));
+        }
+
+        // The portamento time is set before portamento is switched on.
+        if let Some(portamento) = self.portamento {
+            controllers.push((5, portamento));
+            controllers.push((65, 127));
+        }
+
+        controllers
+    }
+
+    /// The bend in cents given by the `bend:` staves at a position, following any gradual bends.
+    pub fn bend_at(&self, position: u32) -> i32 {
+        let mut cents = 0;
+
+        for bend_change in &self.bend_changes {
+            if bend_change.position <= position {
+                cents = bend_change.cents;
+                continue;
+            }
+
+            if let Some(ramp_start) = bend_change.ramp_start {
+                if ramp_start <= position {
+                    let difference = i64::from(bend_change.cents - cents);
+                    let progress = i64::from(position - ramp_start);
+                    let ramp_length = i64::from(bend_change.position - ramp_start);
+
+                    cents += (difference * progress / ramp_length) as i32;
+                }
+            }
+
+            break;
+        }
+
+        cents
+    }
+}
+
 impl<'a> Default for Voice<'a> {
     fn default() -> Self {
         Voice {
@@ -43,7 +209,27 @@ impl<'a> Default for Voice<'a> {
             program: 0,
             transpose: 0,
             volume: None,
+            voicing: Voicing::Close,
+            chord_octave: 0,
+            arpeggio: None,
+            arp_rate: 16,
+            arp_seed: 0,
+            strum: None,
+            strum_direction: StrumDirection::Down,
+            bend_range: 2,
+            pan: None,
+            reverb: None,
+            chorus: None,
+            expression: None,
+            portamento: None,
             notes: Vec::new(),
+            key_changes: Vec::new(),
+            tempo_changes: Vec::new(),
+            dynamic_changes: Vec::new(),
+            bend_changes: Vec::new(),
+            controller_changes: Vec::new(),
+            pressure_changes: Vec::new(),
+            repeat_structure: None,
             divisions_per_bar: 1,
             debug_bar_info: Vec::new(),
         }
@@ -55,6 +241,95 @@ pub struct Note {
     pub position: u32,
     pub length: u32,
     pub midi: Midi,
+
+    // Set by a `vel:` stave. Otherwise, the velocity depends on where the note falls in the bar.
+    pub velocity: Option<u8>,
+
+    pub articulation: Option<Articulation>,
+
+    // Set by a `v` or `^` before a chord. Otherwise, it is strummed in the voice's direction.
+    pub strum: Option<StrumDirection>,
+
+    pub ornament: Option<Ornament>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyChange {
+    pub position: u32,
+    pub key: KeySignature,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TempoChange {
+    pub position: u32,
+    pub tempo: u64,
+
+    // Where a `rit.` or `accel.` begins to change gradually towards this tempo.
+    pub ramp_start: Option<u32>,
+
+    // Whether that change was written as an `accel.`, rather than a `rit.`.
+    pub accelerando: bool,
+}
+
+/// The velocity of `mf`, which a hairpin starts from if no dynamic has been marked before it.
+pub const DEFAULT_DYNAMIC: u8 = 80;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DynamicChange {
+    pub position: u32,
+    pub velocity: u8,
+
+    // Where a `<` or `>` begins to change gradually towards this dynamic.
+    pub ramp_start: Option<u32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BendChange {
+    pub position: u32,
+    pub cents: i32,
+
+    // Where a `/` begins to bend gradually towards this value.
+    pub ramp_start: Option<u32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ControllerChange {
+    pub position: u32,
+    pub controller: u8,
+    pub value: u8,
+
+    // Where a `/` begins to change gradually towards this value.
+    pub ramp_start: Option<u32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PressureChange {
+    pub position: u32,
+
+    // The note which is pressed, or `None` for the pressure of the whole channel.
+    pub note: Option<Midi>,
+    pub pressure: u8,
+
+    // Where a `/` begins to change gradually towards this pressure.
+    pub ramp_start: Option<u32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MeterChange {
+    pub bar: u32,
+    pub beats: u64,
+}
+
+/// The bars of a voice as they are written with repeats, rather than as they are played.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RepeatStructure {
+    // The played bar which is written at each bar.
+    pub bars: Vec<u32>,
+
+    // Each mark, along with the written bar before which it is placed.
+    pub marks: Vec<(u32, Mark)>,
+
+    pub played_bars: u32,
 }
 
 #[derive(Debug, PartialEq)]
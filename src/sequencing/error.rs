@@ -11,11 +11,31 @@ pub struct SequencingError {
 pub enum ErrorType {
     InvalidNote { octave_offset: i8 },
 
+    InvalidChordVoicing { chord_octave: i8 },
+
     UndeclaredVoice { voice_name: String },
 
     VoicelessPlayBlock,
 
     NothingToRepeat,
+
+    UndefinedPart { part_name: String },
+
+    DuplicatePart { part_name: String, definition: SourceLoc },
+
+    RecursivePart { part_name: String, definition: SourceLoc },
+
+    InvalidPartNote { transpose: i8, definition: SourceLoc },
+
+    UndefinedSection { section_name: String },
+
+    DuplicateSection { section_name: String, definition: SourceLoc },
+
+    MismatchedRepeats { other_stave: SourceLoc },
+
+    UnresolvedJump { target: &'static str },
+
+    ConflictingMeterChange { beats: u64, other_beats: u64 },
 }
 
 impl Display for SequencingError {
@@ -37,6 +57,16 @@ impl Display for SequencingError {
                         MAX_SHARP)
             }
 
+            InvalidChordVoicing { chord_octave } => {
+                use notes::{MAX_SHARP, MIN_SHARP};
+
+                format!("Chord `{}` has notes out of range when voiced in octave {}. Notes must lie between `{}` and `{}`.",
+                        self.loc.text(),
+                        chord_octave,
+                        MIN_SHARP,
+                        MAX_SHARP)
+            }
+
             UndeclaredVoice { ref voice_name } => {
                 format!("No voice named `{}` was declared.", voice_name)
             }
@@ -44,6 +74,59 @@ impl Display for SequencingError {
             VoicelessPlayBlock => "Voiceless `play` blocks are not yet supported.".to_owned(),
 
             NothingToRepeat => "There is no previous bar to repeat.".to_owned(),
+
+            UndefinedPart { ref part_name } => {
+                format!("No part named `{}` was defined.", part_name)
+            }
+
+            DuplicatePart { ref part_name, .. } => {
+                format!("A part named `{}` was already defined.", part_name)
+            }
+
+            RecursivePart { ref part_name, .. } => {
+                format!("Part `{}` cannot be played from inside itself.", part_name)
+            }
+
+            InvalidPartNote { transpose, .. } => {
+                use notes::{MAX_SHARP, MIN_SHARP};
+
+                format!("Part `{}` contains notes which are invalid after being transposed by {} semitones. Notes must lie between `{}` and `{}`.",
+                        self.loc.text(),
+                        transpose,
+                        MIN_SHARP,
+                        MAX_SHARP)
+            }
+
+            UndefinedSection { ref section_name } => {
+                format!("No section named `{}` was defined.", section_name)
+            }
+
+            DuplicateSection { ref section_name, .. } => {
+                format!("A section named `{}` was already defined.", section_name)
+            }
+
+            MismatchedRepeats { .. } => "Repeats, endings and navigation marks must be placed at the same bars in every stave of a block.".to_owned(),
+
+            UnresolvedJump { target: "fine" } => {
+                format!("There is no `fine` for `{}` to stop at.", self.loc.text())
+            }
+
+            UnresolvedJump { target: "coda" } => format!(
+                "There is no second `coda` for `{}` to jump to. The first `coda` marks where to jump from.",
+                self.loc.text()
+            ),
+
+            UnresolvedJump { target } => format!(
+                "There is no `{}` for `{}` to jump to.",
+                target,
+                self.loc.text()
+            ),
+
+            ConflictingMeterChange { beats, other_beats } => format!(
+                "This block changes to {} beats per bar at the same bar as another block changes to {}. Every voice must have the same meter.",
+                beats,
+                other_beats
+            ),
         };
 
         error::fmt_error(
@@ -54,6 +137,26 @@ impl Display for SequencingError {
             self.loc.line,
             self.loc.col,
             self.loc.width,
-        )
+        )?;
+
+        match self.error {
+            DuplicatePart { ref definition, .. } => {
+                error::fmt_note(f, "The part was first defined here.", definition)
+            }
+
+            RecursivePart { ref definition, .. } | InvalidPartNote { ref definition, .. } => {
+                error::fmt_note(f, "The part is defined here.", definition)
+            }
+
+            DuplicateSection { ref definition, .. } => {
+                error::fmt_note(f, "The section was first defined here.", definition)
+            }
+
+            MismatchedRepeats { ref other_stave } => {
+                error::fmt_note(f, "The repeats of this stave do not match.", other_stave)
+            }
+
+            _ => Ok(()),
+        }
     }
 }
@@ -3,7 +3,8 @@ pub mod error;
 
 use self::data::*;
 use self::error::{ErrorType, SequencingError};
-use error::SourceMap;
+use error::{SourceLoc, SourceMap};
+use notes::{controller_number, gcd, lcm, pressure_note, Midi, Voicing};
 use parsing::data::*;
 use trust::Trust;
 
@@ -11,14 +12,21 @@ pub fn sequence_pieces<'a>(
     parse_tree: &ParseTree<'a>,
     _source_map: &SourceMap,
 ) -> Result<Vec<Piece<'a>>, SequencingError> {
-    use notes::lcm;
-
     let mut pieces = Vec::new();
 
     for piece_node in &parse_tree.pieces {
+        let all_plays = || {
+            piece_node.plays.iter().chain(
+                piece_node
+                    .sections
+                    .iter()
+                    .flat_map(|section| section.plays.iter()),
+            )
+        };
+
         // validation
         {
-            for play in &piece_node.plays {
+            for play in all_plays() {
                 let matched = piece_node
                     .voices
                     .iter()
@@ -37,13 +45,71 @@ pub fn sequence_pieces<'a>(
                     });
                 }
             }
+
+            for (index, part) in piece_node.parts.iter().enumerate() {
+                let earlier_part = piece_node.parts[..index]
+                    .iter()
+                    .find(|earlier_part| earlier_part.name == part.name);
+
+                if let Some(earlier_part) = earlier_part {
+                    return Err(SequencingError {
+                        loc: part.error_loc.as_ref().trust().clone(),
+                        error: ErrorType::DuplicatePart {
+                            part_name: part.name.to_owned(),
+                            definition: earlier_part.error_loc.as_ref().trust().clone(),
+                        },
+                    });
+                }
+            }
+
+            for (index, section) in piece_node.sections.iter().enumerate() {
+                let earlier_section = piece_node.sections[..index]
+                    .iter()
+                    .find(|earlier_section| earlier_section.name == section.name);
+
+                if let Some(earlier_section) = earlier_section {
+                    return Err(SequencingError {
+                        loc: section.error_loc.as_ref().trust().clone(),
+                        error: ErrorType::DuplicateSection {
+                            section_name: section.name.to_owned(),
+                            definition: earlier_section.error_loc.as_ref().trust().clone(),
+                        },
+                    });
+                }
+            }
         }
 
+        // Without an `arrangement:`, each section is played once in the order it was written.
+        let arrangement = if piece_node.arrangement.is_empty() {
+            piece_node.sections.iter().collect()
+        } else {
+            let mut arrangement = Vec::new();
+
+            for (index, &name) in piece_node.arrangement.iter().enumerate() {
+                let section = piece_node
+                    .sections
+                    .iter()
+                    .find(|section| section.name == name)
+                    .ok_or_else(|| SequencingError {
+                        loc: piece_node.arrangement_locs[index].clone(),
+                        error: ErrorType::UndefinedSection {
+                            section_name: name.to_owned(),
+                        },
+                    })?;
+
+                arrangement.push(section);
+            }
+
+            arrangement
+        };
+
         let Piece {
             title,
             composer,
             tempo,
             beats,
+            beat_unit,
+            key,
             ..
         } = Piece::default();
 
@@ -51,6 +117,8 @@ pub fn sequence_pieces<'a>(
         let composer = piece_node.composer.or(composer);
         let tempo = piece_node.tempo.unwrap_or(tempo);
         let beats = piece_node.beats.unwrap_or(beats);
+        let beat_unit = piece_node.beat_unit.unwrap_or(beat_unit);
+        let key = piece_node.key.unwrap_or(key);
 
         let mut voices = Vec::new();
 
@@ -59,6 +127,12 @@ pub fn sequence_pieces<'a>(
                 channel,
                 program,
                 transpose,
+                voicing,
+                chord_octave,
+                arp_rate,
+                arp_seed,
+                strum_direction,
+                bend_range,
                 ..
             } = Voice::default();
 
@@ -67,116 +141,46 @@ pub fn sequence_pieces<'a>(
             let program = voice_node.program.unwrap_or(program);
             let transpose = voice_node.transpose.unwrap_or(transpose);
             let volume = voice_node.volume.map(|vol| f64::from(vol) / 127.0);
+            let voicing = voice_node.voicing.unwrap_or(voicing);
+            let chord_octave = voice_node.chord_octave.unwrap_or(chord_octave);
+            let arpeggio = voice_node.arpeggio;
+            let arp_rate = voice_node.arp_rate.unwrap_or(arp_rate);
+            let arp_seed = voice_node.arp_seed.unwrap_or(arp_seed);
+            let strum = voice_node.strum;
+            let strum_direction = voice_node.strum_direction.unwrap_or(strum_direction);
+            let bend_range = voice_node.bend_range.unwrap_or(bend_range);
+            let pan = voice_node.pan;
+            let reverb = voice_node.reverb;
+            let chorus = voice_node.chorus;
+            let expression = voice_node.expression;
+            let portamento = voice_node.portamento;
 
-            let divisions_per_bar = piece_node
-                .plays
-                .iter()
+            let mut divisions_per_bar = all_plays()
                 .filter(|play| play.voice == Some(name))
-                .flat_map(|play| {
-                    play.staves.iter().flat_map(|stave| {
-                        stave.bars.iter().map(|bar_type| match *bar_type {
-                            BarTypeNode::Bar(ref bar) => {
-                                bar.notes.iter().map(|note| note.length()).sum()
-                            }
-                            BarTypeNode::RepeatBar => 1,
-                        })
-                    })
-                }).fold(1, lcm);
-
-            let mut notes: Vec<Note> = Vec::new();
-            let mut debug_bar_info: Vec<DebugBarInfo> = Vec::new();
-
-            for play_node in &piece_node.plays {
-                if play_node.voice != Some(name) {
-                    continue;
-                }
-
-                for stave_node in &play_node.staves {
-                    let mut previous_note_exists = false;
-
-                    for (index, bar_node) in stave_node.bars.iter().enumerate() {
-                        let mut cursor = index as u32 * divisions_per_bar;
-
-                        let bar_node = match *bar_node {
-                            BarTypeNode::Bar(ref bar) => bar,
-                            BarTypeNode::RepeatBar => {
-                                previous_note_exists = false;
-
-                                let mut previous_bar = None;
-                                let mut previous_index = index;
-                                while previous_index > 0 {
-                                    previous_index -= 1;
-                                    match stave_node.bars[previous_index] {
-                                        BarTypeNode::Bar(ref bar) => {
-                                            previous_bar = Some(bar);
-                                            break;
-                                        }
-                                        BarTypeNode::RepeatBar => (),
-                                    }
-                                }
-
-                                previous_bar.ok_or_else(|| SequencingError {
-                                    loc: stave_node.bar_locs[index].clone(),
-                                    error: ErrorType::NothingToRepeat,
-                                })?
-                            }
-                        };
+                .map(|play| bar_divisions(&play.staves, &piece_node.parts, &mut Vec::new()))
+                .fold(1, lcm);
 
-                        let bar_node_length: u32 =
-                            bar_node.notes.iter().map(|note| note.length()).sum();
-
-                        let bar_info = DebugBarInfo {
-                            loc: bar_node.note_locs[0].clone(),
-                            divisions_in_source: bar_node_length,
-                        };
-                        debug_bar_info.push(bar_info);
+            let ornamented = all_plays()
+                .filter(|play| play.voice == Some(name))
+                .any(|play| has_ornaments(&play.staves, &piece_node.parts, &mut Vec::new()));
 
-                        assert!(divisions_per_bar % bar_node_length == 0);
-                        let note_scale = divisions_per_bar / bar_node_length;
+            // Every bar must fit a whole number of arpeggiated notes and ornament notes, whatever
+            // its meter.
+            let rates = arpeggio
+                .map(|_| arp_rate)
+                .into_iter()
+                .chain(if ornamented { Some(ORNAMENT_RATE) } else { None });
 
-                        for (note_index, &note_node) in bar_node.notes.iter().enumerate() {
-                            match note_node {
-                                NoteNode::Rest { length } => {
-                                    previous_note_exists = false;
-                                    cursor += note_scale * u32::from(length);
-                                }
-                                NoteNode::Extension { length } => {
-                                    if previous_note_exists {
-                                        let previous_note = notes.last_mut().trust();
-                                        previous_note.length += note_scale * u32::from(length);
-                                    }
+            for rate in rates {
+                let meters = all_plays()
+                    .flat_map(|play| &play.meter_changes)
+                    .map(|meter_change| meter_change.beats)
+                    .chain(Some(beats));
 
-                                    cursor += note_scale * u32::from(length);
-                                }
-                                NoteNode::Note { midi, length } => {
-                                    previous_note_exists = true;
-
-                                    let midi =
-                                        midi.transposed(transpose).ok_or(SequencingError {
-                                            loc: bar_node.note_locs[note_index].clone(),
-                                            error: ErrorType::InvalidNote {
-                                                octave_offset: transpose / 12,
-                                            },
-                                        })?;
-
-                                    let length = note_scale * u32::from(length);
-                                    let position = cursor;
-                                    let note = Note {
-                                        midi,
-                                        length,
-                                        position,
-                                    };
-
-                                    notes.push(note);
-
-                                    cursor += length;
-                                }
-                            }
-                        }
-                    }
+                for bar_beats in meters {
+                    let steps = bar_beats as u32 * rate;
+                    divisions_per_bar = lcm(divisions_per_bar, steps / gcd(steps, beat_unit as u32));
                 }
-
-                notes.sort_by_key(|note| note.position);
             }
 
             let voice = Voice {
@@ -185,387 +189,3764 @@ pub fn sequence_pieces<'a>(
                 program,
                 transpose,
                 volume,
+                voicing,
+                chord_octave,
+                arpeggio,
+                arp_rate,
+                arp_seed,
+                strum,
+                strum_direction,
+                bend_range,
+                pan,
+                reverb,
+                chorus,
+                expression,
+                portamento,
                 divisions_per_bar,
-                notes,
-                debug_bar_info,
+                ..Default::default()
             };
 
             voices.push(voice);
         }
 
-        let piece = Piece {
+        let mut repeat_structures = voices
+            .iter()
+            .map(|_| Some(RepeatStructure::default()))
+            .collect::<Vec<_>>();
+
+        let mut meter_changes = Vec::new();
+
+        for play_node in &piece_node.plays {
+            let index = voices
+                .iter()
+                .position(|voice| Some(voice.name) == play_node.voice)
+                .trust();
+
+            sequence_play(
+                play_node,
+                &piece_node.parts,
+                &mut voices[index],
+                &mut repeat_structures[index],
+                &mut meter_changes,
+                0,
+            )?;
+        }
+
+        let mut section_start = 0;
+
+        for section in arrangement {
+            let mut section_bars = 0;
+
+            for play_node in &section.plays {
+                let index = voices
+                    .iter()
+                    .position(|voice| Some(voice.name) == play_node.voice)
+                    .trust();
+
+                let bars = sequence_play(
+                    play_node,
+                    &piece_node.parts,
+                    &mut voices[index],
+                    &mut repeat_structures[index],
+                    &mut meter_changes,
+                    section_start,
+                )?;
+                section_bars = ::std::cmp::max(section_bars, bars);
+            }
+
+            section_start += section_bars;
+        }
+
+        for (voice, repeat_structure) in voices.iter_mut().zip(repeat_structures) {
+            voice.notes.sort_by_key(|note| note.position);
+            voice
+                .key_changes
+                .sort_by_key(|key_change| key_change.position);
+            voice
+                .tempo_changes
+                .sort_by_key(|tempo_change| tempo_change.position);
+            voice
+                .dynamic_changes
+                .sort_by_key(|dynamic_change| dynamic_change.position);
+            voice
+                .bend_changes
+                .sort_by_key(|bend_change| bend_change.position);
+            voice
+                .controller_changes
+                .sort_by_key(|controller_change| controller_change.position);
+            voice
+                .pressure_changes
+                .sort_by_key(|pressure_change| pressure_change.position);
+
+            voice.repeat_structure =
+                repeat_structure.and_then(|structure| if structure.marks.is_empty() {
+                    None
+                } else {
+                    Some(structure)
+                });
+        }
+
+        // Meter changes apply to every voice, so the same change may be made by several voices.
+        meter_changes.sort_by_key(|meter_change: &MeterChange| meter_change.bar);
+        meter_changes.dedup();
+
+        let mut piece = Piece {
             title,
             composer,
             beats,
+            beat_unit,
             tempo,
+            key,
+            meter_changes,
             voices,
         };
 
+        // Arpeggios depend on the meter of each bar, so they are played once every meter change
+        // is known.
+        let arpeggiated_notes = piece
+            .voices
+            .iter()
+            .map(|voice| arpeggiate(voice, &piece))
+            .collect::<Vec<_>>();
+
+        for (voice, notes) in piece.voices.iter_mut().zip(arpeggiated_notes) {
+            voice.notes = notes;
+        }
+
         pieces.push(piece);
     }
 
     Ok(pieces)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use lexing;
-    use parsing;
-    use test_helpers::midi;
+/// A small deterministic random number generator (SplitMix64), so that random arpeggios are the
+/// same each time a piece is compiled.
+struct Random(u64);
 
-    fn sequence_test(source: &str, expected: Piece) {
-        let (tokens, source_map) = lexing::lex(source, None).expect("ERROR IN LEXER");
-        let parse_tree = parsing::parse(&tokens, &source_map).expect("ERROR IN PARSER");
-        let piece = &sequence_pieces(&parse_tree, &source_map).unwrap()[0];
-        assert_eq!(piece, &expected);
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
     }
+}
 
-    fn sequence_test_fail(source: &str) {
-        let (tokens, source_map) = lexing::lex(source, None).expect("ERROR IN LEXER");
-        let parse_tree = parsing::parse(&tokens, &source_map).expect("ERROR IN PARSER");
-        assert!(sequence_pieces(&parse_tree, &source_map).is_err());
-    }
+/// The notes of a voice, with each group of notes which start together broken into a pattern of
+/// shorter notes, following the voice's arpeggio.
+fn arpeggiate(voice: &Voice, piece: &Piece) -> Vec<Note> {
+    let arpeggio = match voice.arpeggio {
+        Some(arpeggio) => arpeggio,
+        None => return voice.notes.clone(),
+    };
 
-    fn voice_test(source: &str, expected_notes: Vec<Note>) {
-        let (tokens, source_map) = lexing::lex(source, None).expect("ERROR IN LEXER");
-        let parse_tree = parsing::parse(&tokens, &source_map).expect("ERROR IN PARSER");
-        let piece = &sequence_pieces(&parse_tree, &source_map).unwrap()[0];
-        assert_eq!(piece.voices[0].notes, expected_notes);
-    }
+    let mut random = Random(voice.arp_seed);
+    let mut notes = Vec::new();
+    let mut group_start = 0;
 
-    #[test]
-    fn sequence_empty_piece() {
-        sequence_test("", Piece::default());
-    }
+    while group_start < voice.notes.len() {
+        let position = voice.notes[group_start].position;
+        let group_end = voice.notes[group_start..]
+            .iter()
+            .position(|note| note.position != position)
+            .map_or(voice.notes.len(), |offset| group_start + offset);
 
-    #[test]
-    fn piece_with_attributes() {
-        sequence_test(
-            "piece { title: One, composer: Two, tempo: 3, beats: 4 }",
-            Piece {
-                title: Some("One"),
-                composer: Some("Two"),
-                tempo: 3,
-                beats: 4,
-                ..Default::default()
-            },
-        );
+        // Grace notes are left to lead into the arpeggio.
+        let (grace_notes, mut group): (Vec<Note>, Vec<Note>) = voice.notes
+            [group_start..group_end]
+            .iter()
+            .partition(|note| note.ornament == Some(Ornament::GraceNote));
+        notes.extend(grace_notes);
+        group_start = group_end;
+
+        if group.len() < 2 {
+            notes.extend(group);
+            continue;
+        }
+
+        group.sort_by_key(|note| note.midi.midi());
+        let pattern = match arpeggio {
+            Arpeggio::Up | Arpeggio::Random => group.clone(),
+            Arpeggio::Down => group.iter().rev().cloned().collect(),
+            Arpeggio::UpDown => group
+                .iter()
+                .chain(group[1..(group.len() - 1)].iter().rev())
+                .cloned()
+                .collect(),
+        };
+
+        let bar = position / voice.divisions_per_bar;
+        let bar_steps = piece.beats_in_bar(bar) as u32 * voice.arp_rate;
+        let step = voice.divisions_per_bar * piece.beat_unit as u32 / bar_steps;
+        let length = group.iter().map(|note| note.length).max().trust();
+
+        for (index, offset) in (0..length).step_by(step as usize).enumerate() {
+            let index = match arpeggio {
+                Arpeggio::Random => (random.next() % pattern.len() as u64) as usize,
+                _ => index % pattern.len(),
+            };
+
+            notes.push(Note {
+                position: position + offset,
+                length: ::std::cmp::min(step, length - offset),
+                ..pattern[index]
+            });
+        }
     }
 
-    #[test]
-    fn piece_with_empty_voice() {
-        sequence_test(
-            "voice Empty { }",
-            Piece {
-                voices: vec![Voice {
-                    name: "Empty",
-                    ..Default::default()
-                }],
-                ..Default::default()
-            },
-        );
+    notes
+}
+
+/// The number of the notes which ornaments are played with that fit in a whole note.
+const ORNAMENT_RATE: u32 = 32;
+
+/// The notes of a voice as they are played, with each ornament expanded into short notes. These
+/// always take their time from the start of the note they decorate: any grace notes are played
+/// first, then the ornament, and the note itself takes whatever time is left. If that would leave
+/// no time for the note, it is played without its ornament or grace notes.
+pub fn play_ornaments(voice: &Voice, piece: &Piece) -> Vec<Note> {
+    let mut notes = Vec::new();
+    let mut grace_notes = Vec::new();
+
+    for note in &voice.notes {
+        if note.ornament == Some(Ornament::GraceNote) {
+            grace_notes.push(note.midi);
+            continue;
+        }
+
+        let bar = note.position / voice.divisions_per_bar;
+        let bar_steps = piece.beats_in_bar(bar) as u32 * ORNAMENT_RATE;
+        let step = voice.divisions_per_bar * piece.beat_unit as u32 / bar_steps;
+        let steps = note.length.checked_div(step).unwrap_or(0);
+
+        // The neighbouring notes played before the note settles, in semitones from it.
+        let offsets = match note.ornament {
+            Some(Ornament::Trill { upper }) => {
+                let remaining_steps = steps.saturating_sub(grace_notes.len() as u32);
+                let mut offsets = vec![upper];
+                for _ in 1..(remaining_steps / 2) {
+                    offsets.extend(&[0, upper]);
+                }
+                offsets
+            }
+            Some(Ornament::Mordent { lower }) => vec![0, lower],
+            Some(Ornament::Turn { upper, lower }) => vec![upper, 0, lower],
+            Some(Ornament::Glissando { .. }) | Some(Ornament::GraceNote) | None => Vec::new(),
+        };
+
+        let lead_in = grace_notes
+            .drain(..)
+            .map(Some)
+            .chain(offsets.into_iter().map(|offset| note.midi.transposed(offset)))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+
+        let lead_in_length = lead_in.len() as u32 * step;
+        if lead_in_length >= note.length {
+            notes.push(*note);
+            continue;
+        }
+
+        for (index, &midi) in lead_in.iter().enumerate() {
+            notes.push(Note {
+                position: note.position + index as u32 * step,
+                length: step,
+                midi,
+                articulation: None,
+                ornament: None,
+                ..*note
+            });
+        }
+
+        // A glissando slides from the note itself, once any grace notes have been played.
+        let ornament = match note.ornament {
+            Some(Ornament::Glissando { .. }) => note.ornament,
+            _ => None,
+        };
+
+        notes.push(Note {
+            position: note.position + lead_in_length,
+            length: note.length - lead_in_length,
+            ornament,
+            ..*note
+        });
     }
 
-    #[test]
-    fn voice_with_mismatched_play() {
-        sequence_test_fail("voice OneNote { } play Different { :| C }");
+    notes.sort_by_key(|note| note.position);
+    notes
+}
+
+/// Sequence a play block into the given voice, starting at the given bar. Returns the number of
+/// bars played.
+fn sequence_play<'a>(
+    play_node: &PlayNode<'a>,
+    parts: &[PartNode<'a>],
+    voice: &mut Voice<'a>,
+    repeat_structure: &mut Option<RepeatStructure>,
+    meter_changes: &mut Vec<MeterChange>,
+    start_bar: u32,
+) -> Result<u32, SequencingError> {
+    let divisions_per_bar = voice.divisions_per_bar;
+    let start = start_bar * divisions_per_bar;
+
+    let context = StaveContext {
+        parts,
+        divisions_per_bar,
+        transpose: voice.transpose,
+        voicing: voice.voicing,
+        chord_octave: voice.chord_octave,
+    };
+
+    let sequenced = sequence_staves(
+        &play_node.staves,
+        &context,
+        &mut Vec::new(),
+        &mut voice.debug_bar_info,
+    )?;
+
+    // A change before an entry happens each time that entry is played.
+    let played_bars = |entry: usize| -> Vec<u32> {
+        match sequenced.bar_starts.get(entry) {
+            Some(&source_bar) => sequenced
+                .playback
+                .iter()
+                .enumerate()
+                .filter(|&(_, &played)| played == source_bar)
+                .map(|(bar, _)| bar as u32)
+                .collect(),
+            None => vec![sequenced.bars],
+        }
+    };
+
+    for key_change in &play_node.key_changes {
+        for bar in played_bars(key_change.bar) {
+            voice.key_changes.push(KeyChange {
+                position: start + bar * divisions_per_bar,
+                key: key_change.key,
+            });
+        }
     }
 
-    #[test]
-    fn voice_with_single_note() {
-        voice_test(
-            "voice OneNote { } play OneNote { :| C }",
-            vec![Note {
-                midi: midi(60),
-                length: 1,
-                position: 0,
-            }],
-        );
+    for tempo_change in &play_node.tempo_changes {
+        for bar in played_bars(tempo_change.bar) {
+            voice.tempo_changes.push(TempoChange {
+                position: start + bar * divisions_per_bar,
+                tempo: tempo_change.tempo,
+                ramp_start: None,
+                accelerando: false,
+            });
+        }
     }
 
-    #[test]
-    fn voice_with_two_notes() {
-        voice_test(
-            "voice TwoNote { } play TwoNote { :| C G }",
-            vec![
-                Note {
-                    midi: midi(60),
-                    length: 1,
-                    position: 0,
-                },
-                Note {
-                    midi: midi(67),
-                    length: 1,
-                    position: 1,
-                },
-            ],
-        );
+    for meter_change in &play_node.meter_changes {
+        for bar in played_bars(meter_change.bar) {
+            let bar = start_bar + bar;
+
+            // Meter changes apply to every voice, so voices changing at the same bar must agree.
+            let other_beats = meter_changes
+                .iter()
+                .find(|other: &&MeterChange| other.bar == bar && other.beats != meter_change.beats)
+                .map(|other| other.beats);
+
+            if let Some(other_beats) = other_beats {
+                return Err(SequencingError {
+                    loc: play_node.error_loc.as_ref().trust().clone(),
+                    error: ErrorType::ConflictingMeterChange {
+                        beats: meter_change.beats,
+                        other_beats,
+                    },
+                });
+            }
+
+            meter_changes.push(MeterChange {
+                bar,
+                beats: meter_change.beats,
+            });
+        }
     }
 
-    #[test]
-    fn voice_with_two_staves() {
-        voice_test(
-            "voice Diad { } play Diad { :| C ; :| G }",
-            vec![
-                Note {
-                    midi: midi(60),
-                    length: 1,
-                    position: 0,
-                },
-                Note {
-                    midi: midi(67),
-                    length: 1,
-                    position: 0,
-                },
-            ],
-        );
+    let mut tempo_values = sequenced
+        .values
+        .iter()
+        .filter(|value| value.stave == "tempo")
+        .collect::<Vec<_>>();
+    tempo_values.sort_by_key(|value| value.position);
+
+    let mut ramp = None;
+    for value in tempo_values {
+        if let Some(kind) = value.ramp {
+            ramp = Some((start + value.position, kind));
+        } else {
+            let ramp = ramp.take();
+            voice.tempo_changes.push(TempoChange {
+                position: start + value.position,
+                tempo: value.value as u64,
+                ramp_start: ramp.map(|(ramp_start, _)| ramp_start),
+                accelerando: ramp.map(|(_, kind)| kind) == Some(RampKind::Accelerando),
+            });
+        }
     }
 
-    #[test]
-    fn threes_against_twos() {
-        voice_test(
-            "voice Diad { } play Diad { :| C E G ; :| c g }",
-            vec![
-                Note {
-                    midi: midi(60),
-                    length: 2,
-                    position: 0,
-                },
-                Note {
-                    midi: midi(72),
-                    length: 3,
-                    position: 0,
-                },
-                Note {
-                    midi: midi(64),
-                    length: 2,
-                    position: 2,
-                },
-                Note {
-                    midi: midi(79),
-                    length: 3,
-                    position: 3,
-                },
-                Note {
-                    midi: midi(67),
-                    length: 2,
-                    position: 4,
-                },
-            ],
-        );
+    let mut dynamic_values = sequenced
+        .values
+        .iter()
+        .filter(|value| value.stave == "dyn")
+        .collect::<Vec<_>>();
+    dynamic_values.sort_by_key(|value| value.position);
+
+    let mut ramp_start = None;
+    for value in dynamic_values {
+        if value.ramp.is_some() {
+            ramp_start = Some(start + value.position);
+        } else {
+            voice.dynamic_changes.push(DynamicChange {
+                position: start + value.position,
+                velocity: value.value as u8,
+                ramp_start: ramp_start.take(),
+            });
+        }
     }
 
-    #[test]
-    fn fail_when_notes_moved_out_of_range() {
-        sequence_test_fail("voice V { octave: 1} play V { :| g#'''}");
+    let mut bend_values = sequenced
+        .values
+        .iter()
+        .filter(|value| value.stave == "bend")
+        .collect::<Vec<_>>();
+    bend_values.sort_by_key(|value| value.position);
+
+    let mut ramp_start = None;
+    for value in bend_values {
+        if value.ramp.is_some() {
+            ramp_start = Some(start + value.position);
+        } else {
+            voice.bend_changes.push(BendChange {
+                position: start + value.position,
+                cents: value.value as i32,
+                ramp_start: ramp_start.take(),
+            });
+        }
     }
 
-    #[test]
+    // A `ped:` stave changes the sustain pedal, which is controller 64.
+    let controller = |stave: &str| match stave {
+        "ped" => Some(64),
+        _ => controller_number(stave),
+    };
+
+    let mut controller_values = sequenced
+        .values
+        .iter()
+        .filter_map(|value| controller(value.stave).map(|controller| (controller, value)))
+        .collect::<Vec<_>>();
+    controller_values.sort_by_key(|&(controller, value)| (controller, value.position));
+
+    let mut ramp_start = None;
+    for (controller, value) in controller_values {
+        if value.ramp.is_some() {
+            ramp_start = Some(start + value.position);
+        } else {
+            voice.controller_changes.push(ControllerChange {
+                position: start + value.position,
+                controller: controller as u8,
+                value: value.value as u8,
+                ramp_start: ramp_start.take(),
+            });
+        }
+    }
+
+    // Notes are pressed as they sound, after the voice is transposed. A note which would be out
+    // of range can never be played, so its pressure is left out.
+    let transpose = voice.transpose;
+    let pressed_note = |stave: &str| match stave {
+        "pressure" => Some(None),
+        _ => pressure_note(stave)
+            .and_then(|note| note.transposed(transpose))
+            .map(Some),
+    };
+
+    let mut pressure_values = sequenced
+        .values
+        .iter()
+        .filter_map(|value| pressed_note(value.stave).map(|note| (note, value)))
+        .collect::<Vec<_>>();
+    pressure_values.sort_by_key(|&(_, value)| (value.stave, value.position));
+
+    let mut ramp_start = None;
+    for (note, value) in pressure_values {
+        if value.ramp.is_some() {
+            ramp_start = Some(start + value.position);
+        } else {
+            voice.pressure_changes.push(PressureChange {
+                position: start + value.position,
+                note,
+                pressure: value.value as u8,
+                ramp_start: ramp_start.take(),
+            });
+        }
+    }
+
+    *repeat_structure = repeat_structure
+        .take()
+        .and_then(|structure| extend_repeat_structure(structure, &sequenced, start_bar));
+
+    // Each velocity lasts until the next one, or until the end of the play block.
+    let mut velocity_values = sequenced
+        .values
+        .iter()
+        .filter(|value| value.stave == "vel")
+        .collect::<Vec<_>>();
+    velocity_values.sort_by_key(|value| value.position);
+
+    voice
+        .notes
+        .extend(sequenced.notes.into_iter().map(|note| {
+            let velocity = velocity_values
+                .iter()
+                .take_while(|value| value.position <= note.position)
+                .last()
+                .map(|value| value.value as u8);
+
+            Note {
+                position: start + note.position,
+                velocity: note.velocity.or(velocity),
+                ..note
+            }
+        }));
+
+    Ok(sequenced.bars)
+}
+
+/// Add the bars of a play block to the repeat structure of its voice. Returns `None` if the
+/// repeats can no longer be written out, such as when play blocks overlap.
+fn extend_repeat_structure(
+    mut structure: RepeatStructure,
+    sequenced: &SequencedStaves,
+    start_bar: u32,
+) -> Option<RepeatStructure> {
+    if start_bar < structure.played_bars {
+        return None;
+    }
+
+    // Only repeats which are played twice can be written.
+    let writable = sequenced.marks.iter().all(|&(_, mark)| match mark {
+        Mark::EndRepeat { count } => count == 2,
+        _ => true,
+    });
+    if !writable {
+        return None;
+    }
+
+    structure.bars.extend(structure.played_bars..start_bar);
+    let written_start = structure.bars.len() as u32;
+
+    for written_bar in 0..sequenced.written_bars {
+        let played_bar = sequenced
+            .playback
+            .iter()
+            .position(|&source_bar| source_bar == written_bar)?;
+        structure.bars.push(start_bar + played_bar as u32);
+    }
+
+    structure.marks.extend(
+        sequenced
+            .marks
+            .iter()
+            .map(|&(bar, mark)| (written_start + bar, mark)),
+    );
+    structure.played_bars = start_bar + sequenced.bars;
+
+    Some(structure)
+}
+
+fn find_part<'p, 'a: 'p>(parts: &'p [PartNode<'a>], name: &str) -> Option<&'p PartNode<'a>> {
+    parts.iter().find(|part| part.name == name)
+}
+
+/// The number of divisions a bar is split into, and the length of each of its notes in those
+/// divisions. Notes in a tuplet share the length of the tuplet between them.
+fn bar_note_lengths(bar: &BarNode) -> (u32, Vec<u32>) {
+    let tuplet_divisions = |tuplet: &TupletNode| -> u32 {
+        bar.notes[tuplet.start..tuplet.end]
+            .iter()
+            .map(|note| note.length())
+            .sum()
+    };
+
+    // Every tuplet must be split into a whole number of divisions.
+    let scale = bar.tuplets.iter().map(&tuplet_divisions).fold(1, lcm);
+
+    let mut lengths = bar
+        .notes
+        .iter()
+        .map(|note| note.length() * scale)
+        .collect::<Vec<_>>();
+
+    for tuplet in &bar.tuplets {
+        let tuplet_scale = u32::from(tuplet.length) * scale / tuplet_divisions(tuplet);
+
+        for (note_length, note) in lengths[tuplet.start..tuplet.end]
+            .iter_mut()
+            .zip(&bar.notes[tuplet.start..tuplet.end])
+        {
+            *note_length = note.length() * tuplet_scale;
+        }
+    }
+
+    (lengths.iter().sum(), lengths)
+}
+
+/// The lowest number of divisions per bar which can fit every bar of the given staves, including
+/// the bars of any parts they play.
+fn bar_divisions<'a>(
+    staves: &[StaveNode<'a>],
+    parts: &[PartNode<'a>],
+    visited_parts: &mut Vec<&'a str>,
+) -> u32 {
+    let mut divisions = 1;
+
+    for stave in staves {
+        for bar_type in &stave.bars {
+            let bar_divisions = match *bar_type {
+                BarTypeNode::Bar(ref bar) => bar_note_lengths(bar).0,
+                BarTypeNode::RepeatBar => 1,
+                BarTypeNode::PlayPart(ref play_part) => {
+                    if visited_parts.contains(&play_part.name) {
+                        continue;
+                    }
+                    visited_parts.push(play_part.name);
+
+                    match find_part(parts, play_part.name) {
+                        Some(part) => bar_divisions(&part.staves, parts, visited_parts),
+                        None => 1,
+                    }
+                }
+            };
+
+            divisions = lcm(divisions, bar_divisions);
+        }
+    }
+
+    divisions
+}
+
+/// Whether any note in the staves, or in the parts they play, is ornamented or has grace notes.
+fn has_ornaments<'a>(
+    staves: &[StaveNode<'a>],
+    parts: &[PartNode<'a>],
+    visited_parts: &mut Vec<&'a str>,
+) -> bool {
+    for stave in staves {
+        for bar_type in &stave.bars {
+            let bar_has_ornaments = match *bar_type {
+                BarTypeNode::Bar(ref bar) => bar.notes.iter().any(|note| match *note {
+                    NoteNode::Note {
+                        ornament,
+                        ref grace_notes,
+                        ..
+                    } => ornament.is_some() || !grace_notes.is_empty(),
+                    _ => false,
+                }),
+                BarTypeNode::RepeatBar => false,
+                BarTypeNode::PlayPart(ref play_part) => {
+                    if visited_parts.contains(&play_part.name) {
+                        continue;
+                    }
+                    visited_parts.push(play_part.name);
+
+                    match find_part(parts, play_part.name) {
+                        Some(part) => has_ornaments(&part.staves, parts, visited_parts),
+                        None => false,
+                    }
+                }
+            };
+
+            if bar_has_ornaments {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+struct StaveContext<'p, 'a: 'p> {
+    parts: &'p [PartNode<'a>],
+    divisions_per_bar: u32,
+    transpose: i8,
+    voicing: Voicing,
+    chord_octave: i8,
+}
+
+/// A number from a stave of values, such as a `tempo:` stave.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct StaveValue<'p> {
+    stave: &'p str,
+    position: u32,
+    length: u32,
+    value: i64,
+
+    // Set for a mark such as `rit.` or `/`, which changes gradually towards the next value.
+    ramp: Option<RampKind>,
+}
+
+struct SequencedStaves<'p> {
+    notes: Vec<Note>,
+    values: Vec<StaveValue<'p>>,
+    bars: u32,
+    written_bars: u32,
+
+    // The bar at which each entry in the longest stave begins, before repeats are expanded.
+    bar_starts: Vec<u32>,
+
+    // The bar as written which is played for each bar, after repeats are expanded.
+    playback: Vec<u32>,
+
+    // The marks of the first stave, along with the bar as written before which they are placed.
+    marks: Vec<(u32, Mark)>,
+}
+
+fn sequence_staves<'p, 'a: 'p>(
+    staves: &'p [StaveNode<'a>],
+    context: &StaveContext<'p, 'a>,
+    part_stack: &mut Vec<&'a str>,
+    debug_bar_info: &mut Vec<DebugBarInfo>,
+) -> Result<SequencedStaves<'p>, SequencingError> {
+    let divisions_per_bar = context.divisions_per_bar;
+
+    let mut notes: Vec<Note> = Vec::new();
+    let mut values: Vec<StaveValue> = Vec::new();
+    let mut bars = 0;
+    let mut bar_starts = Vec::new();
+    let mut stave_marks = Vec::new();
+
+    for stave_node in staves {
+        let mut previous_note_exists = false;
+        // The number of notes played together by the previous note or chord.
+        let mut previous_chord_size = 1;
+        let mut bar_index = 0;
+        let mut entry_starts = Vec::new();
+
+        for (index, bar_node) in stave_node.bars.iter().enumerate() {
+            entry_starts.push(bar_index);
+
+            let mut cursor = bar_index * divisions_per_bar;
+
+            let source_index = match *bar_node {
+                BarTypeNode::RepeatBar => {
+                    previous_note_exists = false;
+
+                    let mut previous_bar = None;
+                    let mut previous_index = index;
+                    while previous_index > 0 {
+                        previous_index -= 1;
+                        match stave_node.bars[previous_index] {
+                            BarTypeNode::RepeatBar => (),
+                            _ => {
+                                previous_bar = Some(previous_index);
+                                break;
+                            }
+                        }
+                    }
+
+                    previous_bar.ok_or_else(|| SequencingError {
+                        loc: stave_node.bar_locs[index].clone(),
+                        error: ErrorType::NothingToRepeat,
+                    })?
+                }
+                _ => index,
+            };
+
+            let bar_node = match stave_node.bars[source_index] {
+                BarTypeNode::Bar(ref bar) => bar,
+                BarTypeNode::PlayPart(ref play_part) => {
+                    previous_note_exists = false;
+
+                    let part_loc = &stave_node.bar_locs[source_index];
+                    let (part_notes, part_values, part_bars) =
+                        sequence_part(play_part, part_loc, context, part_stack, debug_bar_info)?;
+
+                    notes.extend(part_notes.into_iter().map(|note| Note {
+                        position: note.position + cursor,
+                        ..note
+                    }));
+                    values.extend(part_values.into_iter().map(|value| StaveValue {
+                        position: value.position + cursor,
+                        ..value
+                    }));
+
+                    bar_index += part_bars;
+                    continue;
+                }
+                BarTypeNode::RepeatBar => unreachable!(),
+            };
+
+            bar_index += 1;
+
+            let (bar_node_length, note_lengths) = bar_note_lengths(bar_node);
+
+            let bar_info = DebugBarInfo {
+                loc: bar_node.note_locs[0].clone(),
+                divisions_in_source: bar_node_length,
+            };
+            debug_bar_info.push(bar_info);
+
+            assert!(divisions_per_bar % bar_node_length == 0);
+            let note_scale = divisions_per_bar / bar_node_length;
+
+            for (note_index, note_node) in bar_node.notes.iter().enumerate() {
+                match *note_node {
+                    NoteNode::Rest { .. } => {
+                        previous_note_exists = false;
+                        cursor += note_scale * note_lengths[note_index];
+                    }
+                    NoteNode::Extension { .. } => {
+                        let length = note_scale * note_lengths[note_index];
+
+                        if previous_note_exists {
+                            match values.last_mut() {
+                                Some(value) if value.stave == stave_node.prefix => {
+                                    value.length += length;
+                                }
+                                _ => {
+                                    let chord_start = notes.len() - previous_chord_size;
+                                    for note in &mut notes[chord_start..] {
+                                        note.length += length;
+                                    }
+                                }
+                            }
+                        }
+
+                        cursor += length;
+                    }
+                    NoteNode::Note {
+                        midi,
+                        articulation,
+                        ornament,
+                        ref grace_notes,
+                        ..
+                    } => {
+                        previous_note_exists = true;
+                        previous_chord_size = 1;
+
+                        let length = note_scale * note_lengths[note_index];
+                        let position = cursor;
+
+                        let transpose = |midi: Midi| {
+                            midi.transposed(context.transpose).ok_or(SequencingError {
+                                loc: bar_node.note_locs[note_index].clone(),
+                                error: ErrorType::InvalidNote {
+                                    octave_offset: context.transpose / 12,
+                                },
+                            })
+                        };
+
+                        // Grace notes are kept at the position of the note they lead into, so that
+                        // they can be written as they were. They are only moved when played.
+                        for &grace_note in grace_notes {
+                            notes.push(Note {
+                                midi: transpose(grace_note)?,
+                                length,
+                                position,
+                                velocity: None,
+                                articulation: None,
+                                strum: None,
+                                ornament: Some(Ornament::GraceNote),
+                            });
+                        }
+
+                        let note = Note {
+                            midi: transpose(midi)?,
+                            length,
+                            position,
+                            velocity: None,
+                            articulation,
+                            strum: None,
+                            ornament,
+                        };
+
+                        notes.push(note);
+
+                        cursor += length;
+                    }
+                    NoteNode::Chord { .. } | NoteNode::ChordSymbol { .. } => {
+                        let (midis, articulation, strum) = match *note_node {
+                            NoteNode::Chord {
+                                ref midis,
+                                articulation,
+                                strum,
+                                ..
+                            } => (midis.clone(), articulation, strum),
+                            NoteNode::ChordSymbol { symbol, strum, .. } => {
+                                let midis = symbol
+                                    .voiced(context.voicing, context.chord_octave)
+                                    .ok_or_else(|| SequencingError {
+                                        loc: bar_node.note_locs[note_index].clone(),
+                                        error: ErrorType::InvalidChordVoicing {
+                                            chord_octave: context.chord_octave,
+                                        },
+                                    })?;
+                                (midis, None, strum)
+                            }
+                            _ => unreachable!(),
+                        };
+
+                        previous_note_exists = true;
+                        previous_chord_size = midis.len();
+
+                        let length = note_scale * note_lengths[note_index];
+                        for midi in midis {
+                            let midi =
+                                midi.transposed(context.transpose).ok_or(SequencingError {
+                                    loc: bar_node.note_locs[note_index].clone(),
+                                    error: ErrorType::InvalidNote {
+                                        octave_offset: context.transpose / 12,
+                                    },
+                                })?;
+
+                            notes.push(Note {
+                                midi,
+                                length,
+                                position: cursor,
+                                velocity: None,
+                                articulation,
+                                strum,
+                                ornament: None,
+                            });
+                        }
+
+                        cursor += length;
+                    }
+                    NoteNode::Value { value, .. } => {
+                        previous_note_exists = true;
+
+                        let length = note_scale * note_lengths[note_index];
+                        values.push(StaveValue {
+                            stave: &stave_node.prefix,
+                            position: cursor,
+                            length,
+                            value,
+                            ramp: None,
+                        });
+
+                        cursor += length;
+                    }
+                    NoteNode::Ramp { kind, .. } => {
+                        previous_note_exists = true;
+
+                        let length = note_scale * note_lengths[note_index];
+                        values.push(StaveValue {
+                            stave: &stave_node.prefix,
+                            position: cursor,
+                            length,
+                            value: 0,
+                            ramp: Some(kind),
+                        });
+
+                        cursor += length;
+                    }
+                }
+            }
+        }
+
+        bars = ::std::cmp::max(bars, bar_index);
+
+        let marks = stave_node
+            .marks
+            .iter()
+            .map(|mark| {
+                let bar = entry_starts.get(mark.bar).cloned().unwrap_or(bar_index);
+                (bar, mark.mark)
+            }).collect::<Vec<_>>();
+        stave_marks.push(marks);
+
+        if entry_starts.len() > bar_starts.len() {
+            bar_starts = entry_starts;
+        }
+    }
+
+    if bar_starts.is_empty() {
+        bar_starts.push(0);
+    }
+
+    for (stave_index, marks) in stave_marks.iter().enumerate().skip(1) {
+        let expected = &stave_marks[0];
+        if marks == expected {
+            continue;
+        }
+
+        let mismatch = marks
+            .iter()
+            .zip(expected)
+            .position(|(mark, expected_mark)| mark != expected_mark)
+            .unwrap_or_else(|| ::std::cmp::min(marks.len(), expected.len()));
+
+        let stave_loc = |index: usize| {
+            let stave = &staves[index];
+            stave
+                .mark_locs
+                .get(mismatch)
+                .or_else(|| stave.bar_locs.first())
+                .cloned()
+        };
+
+        let (loc, other_stave) = if mismatch < marks.len() {
+            (stave_loc(stave_index), stave_loc(0))
+        } else {
+            (stave_loc(0), stave_loc(stave_index))
+        };
+
+        return Err(SequencingError {
+            loc: loc.trust(),
+            error: ErrorType::MismatchedRepeats {
+                other_stave: other_stave.or_else(|| stave_loc(0)).trust(),
+            },
+        });
+    }
+
+    let playback = match (stave_marks.first(), staves.first()) {
+        (Some(marks), Some(stave)) if !marks.is_empty() => {
+            playback_order(marks, &stave.mark_locs, bars)?
+        }
+        _ => (0..bars).collect(),
+    };
+
+    let expanded = playback.len() as u32 != bars
+        || playback
+            .iter()
+            .enumerate()
+            .any(|(bar, &source_bar)| bar as u32 != source_bar);
+
+    if expanded {
+        notes = expand_playback(notes, &playback, bars, divisions_per_bar, |note| {
+            &mut note.position
+        });
+        values = expand_playback(values, &playback, bars, divisions_per_bar, |value| {
+            &mut value.position
+        });
+    }
+
+    Ok(SequencedStaves {
+        notes,
+        values,
+        bars: playback.len() as u32,
+        written_bars: bars,
+        bar_starts,
+        playback,
+        marks: stave_marks.into_iter().next().unwrap_or_default(),
+    })
+}
+
+/// Lay out the bars of a block in the order they are played.
+fn expand_playback<T, F>(
+    items: Vec<T>,
+    playback: &[u32],
+    bars: u32,
+    divisions_per_bar: u32,
+    position: F,
+) -> Vec<T>
+where
+    T: Clone,
+    F: Fn(&mut T) -> &mut u32,
+{
+    let mut bar_items = vec![Vec::new(); bars as usize];
+    for mut item in items {
+        let bar = *position(&mut item) / divisions_per_bar;
+        bar_items[bar as usize].push(item);
+    }
+
+    let mut expanded = Vec::new();
+    for (bar, &source_bar) in playback.iter().enumerate() {
+        let bar_start = bar as u32 * divisions_per_bar;
+        let source_start = source_bar * divisions_per_bar;
+
+        for item in &bar_items[source_bar as usize] {
+            let mut item = item.clone();
+            *position(&mut item) += bar_start;
+            *position(&mut item) -= source_start;
+            expanded.push(item);
+        }
+    }
+
+    expanded
+}
+
+/// The order in which the bars of a block are played, once its repeats and jumps are expanded.
+fn playback_order(
+    marks: &[(u32, Mark)],
+    mark_locs: &[SourceLoc],
+    bars: u32,
+) -> Result<Vec<u32>, SequencingError> {
+    fn is_volta(mark: Mark) -> bool {
+        matches!(mark, Mark::Volta { .. })
+    }
+
+    let unresolved_jump = |index: usize, target: &'static str| SequencingError {
+        loc: mark_locs[index].clone(),
+        error: ErrorType::UnresolvedJump { target },
+    };
+
+    let segno = marks.iter().position(|&(_, mark)| mark == Mark::Segno);
+    let to_coda = marks.iter().position(|&(_, mark)| mark == Mark::Coda);
+    let coda = to_coda.and_then(|to_coda| {
+        marks[(to_coda + 1)..]
+            .iter()
+            .position(|&(_, mark)| mark == Mark::Coda)
+            .map(|offset| to_coda + 1 + offset)
+    });
+    let fine = marks.iter().position(|&(_, mark)| mark == Mark::Fine);
+
+    for (index, &(_, mark)) in marks.iter().enumerate() {
+        let until = match mark {
+            Mark::DaCapo { until } => until,
+            Mark::DalSegno { until } => {
+                if segno.is_none() {
+                    return Err(unresolved_jump(index, "segno"));
+                }
+                until
+            }
+            _ => continue,
+        };
+
+        match until {
+            Until::Fine if fine.is_none() => return Err(unresolved_jump(index, "fine")),
+            Until::Coda if coda.is_none() => return Err(unresolved_jump(index, "coda")),
+            _ => (),
+        }
+    }
+
+    let mut playback = Vec::new();
+    let mut bar = 0;
+    let mut index = 0;
+
+    // The bar and mark index to return to at the end of a repeat.
+    let mut repeat_start = (0, 0);
+    let mut pass = 1;
+    let mut in_ending = false;
+
+    // Once a `D.C.` or `D.S.` has been taken, repeats are no longer played.
+    let mut jumped_until = None;
+    let mut jumps_taken = Vec::new();
+
+    while index < marks.len() {
+        let (mark_bar, mark) = marks[index];
+        playback.extend(bar..mark_bar);
+        bar = mark_bar;
+        index += 1;
+
+        match mark {
+            Mark::StartRepeat => {
+                repeat_start = (bar, index);
+                pass = 1;
+                in_ending = false;
+            }
+            Mark::EndRepeat { count } => {
+                if !in_ending && repeat_start.0 == bar {
+                    return Err(SequencingError {
+                        loc: mark_locs[index - 1].clone(),
+                        error: ErrorType::NothingToRepeat,
+                    });
+                }
+
+                if jumped_until.is_none() && (in_ending || pass < count) {
+                    pass += 1;
+                    bar = repeat_start.0;
+                    index = repeat_start.1;
+                } else {
+                    repeat_start = (bar, index);
+                    pass = 1;
+                }
+
+                in_ending = false;
+            }
+            Mark::Volta { number } => {
+                // An ending closed by a `:|` is only played on its own pass. The last ending has
+                // no `:|`, and is always played.
+                let closing_repeat = marks[index..]
+                    .iter()
+                    .take_while(|&&(_, mark)| !is_volta(mark) && mark != Mark::StartRepeat)
+                    .position(|&(_, mark)| matches!(mark, Mark::EndRepeat { .. }));
+
+                match closing_repeat {
+                    Some(offset) if number != pass || jumped_until.is_some() => {
+                        index += offset;
+                        bar = marks[index].0;
+                        index += 1;
+
+                        let next_is_volta = match marks.get(index) {
+                            Some(&(next_bar, mark)) => next_bar == bar && is_volta(mark),
+                            None => false,
+                        };
+
+                        if !next_is_volta {
+                            repeat_start = (bar, index);
+                            pass = 1;
+                        }
+                    }
+                    Some(_) => in_ending = true,
+                    None => {
+                        repeat_start = (bar, index);
+                        pass = 1;
+                        in_ending = false;
+                    }
+                }
+            }
+            Mark::Segno => (),
+            Mark::Coda => {
+                if jumped_until == Some(Until::Coda) && Some(index - 1) == to_coda {
+                    index = coda.trust();
+                    bar = marks[index].0;
+                    index += 1;
+                }
+            }
+            Mark::Fine => {
+                if jumped_until == Some(Until::Fine) {
+                    return Ok(playback);
+                }
+            }
+            Mark::DaCapo { until } | Mark::DalSegno { until } => {
+                if jumps_taken.contains(&(index - 1)) {
+                    continue;
+                }
+                jumps_taken.push(index - 1);
+                jumped_until = Some(until);
+
+                let (target_bar, target_index) = match mark {
+                    Mark::DalSegno { .. } => {
+                        let segno = segno.trust();
+                        (marks[segno].0, segno + 1)
+                    }
+                    _ => (0, 0),
+                };
+
+                bar = target_bar;
+                index = target_index;
+                repeat_start = (bar, index);
+                pass = 1;
+                in_ending = false;
+            }
+        }
+    }
+
+    playback.extend(bar..bars);
+
+    Ok(playback)
+}
+
+fn sequence_part<'p, 'a: 'p>(
+    play_part: &PlayPartNode<'a>,
+    part_loc: &SourceLoc,
+    context: &StaveContext<'p, 'a>,
+    part_stack: &mut Vec<&'a str>,
+    debug_bar_info: &mut Vec<DebugBarInfo>,
+) -> Result<(Vec<Note>, Vec<StaveValue<'p>>, u32), SequencingError> {
+    let part = find_part(context.parts, play_part.name).ok_or_else(|| SequencingError {
+        loc: part_loc.clone(),
+        error: ErrorType::UndefinedPart {
+            part_name: play_part.name.to_owned(),
+        },
+    })?;
+
+    let definition = part.error_loc.as_ref().trust();
+
+    if part_stack.contains(&play_part.name) {
+        return Err(SequencingError {
+            loc: part_loc.clone(),
+            error: ErrorType::RecursivePart {
+                part_name: play_part.name.to_owned(),
+                definition: definition.clone(),
+            },
+        });
+    }
+
+    part_stack.push(play_part.name);
+    let sequenced = sequence_staves(&part.staves, context, part_stack, debug_bar_info)?;
+    part_stack.pop();
+
+    let part_length = sequenced.bars * context.divisions_per_bar;
+    let augmentation = play_part.augmentation;
+
+    // Each velocity lasts until the next one, or until the end of the part.
+    let mut velocity_values = sequenced
+        .values
+        .iter()
+        .filter(|value| value.stave == "vel")
+        .collect::<Vec<_>>();
+    velocity_values.sort_by_key(|value| value.position);
+
+    let mut notes = Vec::new();
+    for note in sequenced.notes {
+        let velocity = velocity_values
+            .iter()
+            .take_while(|value| value.position <= note.position)
+            .last()
+            .map(|value| value.value as u8);
+
+        let position = if play_part.retrograde {
+            part_length - note.position - note.length
+        } else {
+            note.position
+        };
+
+        let midi = note
+            .midi
+            .transposed(play_part.transpose)
+            .ok_or_else(|| SequencingError {
+                loc: part_loc.clone(),
+                error: ErrorType::InvalidPartNote {
+                    transpose: play_part.transpose,
+                    definition: definition.clone(),
+                },
+            })?;
+
+        notes.push(Note {
+            position: position * augmentation,
+            length: note.length * augmentation,
+            midi,
+            velocity: note.velocity.or(velocity),
+            articulation: note.articulation,
+            strum: note.strum,
+            ornament: note.ornament,
+        });
+    }
+
+    notes.sort_by_key(|note| note.position);
+
+    let mut values = sequenced
+        .values
+        .into_iter()
+        .filter(|value| value.stave != "vel")
+        .map(|value| {
+            let position = if play_part.retrograde {
+                part_length - value.position - value.length
+            } else {
+                value.position
+            };
+
+            StaveValue {
+                position: position * augmentation,
+                length: value.length * augmentation,
+                ..value
+            }
+        }).collect::<Vec<_>>();
+
+    values.sort_by_key(|value| value.position);
+
+    Ok((notes, values, sequenced.bars * augmentation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexing;
+    use parsing;
+    use test_helpers::midi;
+
+    fn sequence(source: &str) -> Piece {
+        let (tokens, source_map) = lexing::lex(source, None).expect("ERROR IN LEXER");
+
+        // The piece borrows from the tokens, so they are kept until the test ends.
+        let tokens = Box::leak(tokens.into_boxed_slice());
+        let parse_tree = parsing::parse(tokens, &source_map).expect("ERROR IN PARSER");
+        sequence_pieces(&parse_tree, &source_map).unwrap().remove(0)
+    }
+
+    fn sequence_test(source: &str, expected: Piece) {
+        assert_eq!(sequence(source), expected);
+    }
+
+    fn sequence_test_fail(source: &str) {
+        let (tokens, source_map) = lexing::lex(source, None).expect("ERROR IN LEXER");
+        let parse_tree = parsing::parse(&tokens, &source_map).expect("ERROR IN PARSER");
+        assert!(sequence_pieces(&parse_tree, &source_map).is_err());
+    }
+
+    fn voice_test(source: &str, expected_notes: Vec<Note>) {
+        let piece = sequence(source);
+        assert_eq!(piece.voices[0].notes, expected_notes);
+    }
+
+    #[test]
+    fn sequence_empty_piece() {
+        sequence_test("", Piece::default());
+    }
+
+    #[test]
+    fn piece_with_attributes() {
+        sequence_test(
+            "piece { title: One, composer: Two, tempo: 3, beats: 4 }",
+            Piece {
+                title: Some("One"),
+                composer: Some("Two"),
+                tempo: 3,
+                beats: 4,
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn piece_with_compound_meter() {
+        let source = "meter: 6/8\nvoice A {} play A { :| C . . D . . |\n\nbeats: 9\n\n:| C }";
+        let piece = sequence(source);
+
+        assert_eq!((piece.beats, piece.beat_unit), (6, 8));
+        assert_eq!(piece.tempo_beat(), (3, 8));
+        assert_eq!(piece.felt_beats_in_bar(0), 2);
+        assert_eq!(piece.felt_beat_in_bar(1), (3, 8));
+        assert_eq!(piece.felt_beats_in_bar(1), 3);
+        assert_eq!(Piece::default().tempo_beat(), (1, 4));
+
+        let six_four = Piece {
+            beats: 6,
+            ..Default::default()
+        };
+        assert_eq!(six_four.tempo_beat(), (1, 4));
+        assert_eq!(six_four.felt_beats_in_bar(0), 6);
+    }
+
+    #[test]
+    fn piece_with_empty_voice() {
+        sequence_test(
+            "voice Empty { }",
+            Piece {
+                voices: vec![Voice {
+                    name: "Empty",
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn voice_with_mismatched_play() {
+        sequence_test_fail("voice OneNote { } play Different { :| C }");
+    }
+
+    #[test]
+    fn voice_with_single_note() {
+        voice_test(
+            "voice OneNote { } play OneNote { :| C }",
+            vec![Note {
+                midi: midi(60),
+                length: 1,
+                position: 0,
+                velocity: None,
+                articulation: None,
+                strum: None,
+                ornament: None,
+            }],
+        );
+    }
+
+    #[test]
+    fn voice_with_two_notes() {
+        voice_test(
+            "voice TwoNote { } play TwoNote { :| C G }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn voice_with_two_staves() {
+        voice_test(
+            "voice Diad { } play Diad { :| C ; :| G }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn threes_against_twos() {
+        voice_test(
+            "voice Diad { } play Diad { :| C E G ; :| c g }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 2,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(72),
+                    length: 3,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 2,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(79),
+                    length: 3,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 2,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn fail_when_notes_moved_out_of_range() {
+        sequence_test_fail("voice V { octave: 1} play V { :| g#'''}");
+    }
+
+    #[test]
     fn voice_with_note_lengths() {
         voice_test(
-            "voice A { } play A { :| C4 -2 G2 }",
+            "voice A { } play A { :| C4 -2 G2 }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 4,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 2,
+                    position: 6,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn voice_with_dots() {
+        voice_test(
+            "voice A { } play A { :| A..B C... .8 }",
+            vec![
+                Note {
+                    midi: midi(57),
+                    length: 3,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(59),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 12,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn voice_with_leading_dots() {
+        voice_test(
+            "voice A { } play A { :| ...C E... G... -... }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 4,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 4,
+                    position: 8,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn dots_do_not_carry_across_staves() {
+        voice_test(
+            "voice A { } play A { :| CEGc | ; :| ...g }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(72),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(79),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn notes_can_be_tied_across_bars() {
+        voice_test(
+            "voice A {} play A { :| CEG. | ..EC }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 4,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 6,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 7,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat_bars() {
+        voice_test(
+            "voice A {} play A { :| A C | % | }",
+            vec![
+                Note {
+                    midi: midi(57),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(57),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat_bars_twice() {
+        voice_test(
+            "voice A {} play A { :| A C | % | % | }",
+            vec![
+                Note {
+                    midi: midi(57),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(57),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(57),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 5,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn fail_first_bar_repeat() {
+        sequence_test_fail("voice A {} play A { :| % | }");
+    }
+
+    #[test]
+    fn play_part() {
+        voice_test(
+            "part Riff { :| C E } voice A {} play A { :| *Riff | G }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 2,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn play_multiple_bar_part() {
+        voice_test(
+            "part Riff { :| C | E } voice A {} play A { :| *Riff | G }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn play_transposed_part() {
+        voice_test(
+            "part Riff { :| C E } voice A {} play A { :| *Riff+5 }",
+            vec![
+                Note {
+                    midi: midi(65),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(69),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn play_retrograde_part() {
+        voice_test(
+            "part Riff { :| C E - } voice A {} play A { :| *Riff< }",
+            vec![
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn play_augmented_part() {
+        voice_test(
+            "part Riff { :| C E } voice A {} play A { :| *Riff*2 | G }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 2,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 2,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 2,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat_bar_after_part() {
+        voice_test(
+            "part Riff { :| C } voice A {} play A { :| *Riff | % }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn fail_undefined_part() {
+        sequence_test_fail("voice A {} play A { :| *Riff }");
+    }
+
+    #[test]
+    fn fail_recursive_part() {
+        sequence_test_fail("part A { :| *B } part B { :| C | *A } voice V {} play V { :| *A }");
+    }
+
+    #[test]
+    fn fail_duplicate_part() {
+        sequence_test_fail("part A { :| C } part A { :| D }");
+    }
+
+    #[test]
+    fn fail_when_part_transposed_out_of_range() {
+        sequence_test_fail("part A { :| C } voice V {} play V { :| *A+100 }");
+    }
+
+    #[test]
+    fn sections_follow_arrangement() {
+        let source = "
+            voice A {}
+            voice B {}
+            arrangement: Verse Chorus Verse
+
+            section Verse {
+                play A { :| C | D }
+                play B { :| E }
+            }
+
+            section Chorus {
+                play B { :| G }
+            }
+        ";
+
+        let (tokens, source_map) = lexing::lex(source, None).unwrap();
+        let parse_tree = parsing::parse(&tokens, &source_map).unwrap();
+        let piece = &sequence_pieces(&parse_tree, &source_map).unwrap()[0];
+
+        let positions = |voice: &Voice| -> Vec<(i8, u32)> {
+            voice
+                .notes
+                .iter()
+                .map(|note| (note.midi.midi(), note.position))
+                .collect()
+        };
+
+        assert_eq!(positions(&piece.voices[0]), vec![(60, 0), (62, 1), (60, 3), (62, 4)]);
+        assert_eq!(positions(&piece.voices[1]), vec![(64, 0), (67, 2), (64, 3)]);
+    }
+
+    #[test]
+    fn sections_play_in_order_without_arrangement() {
+        voice_test(
+            "voice A {} section One { play A { :| C } } section Two { play A { :| D } }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn fail_undefined_section() {
+        sequence_test_fail("voice A {} arrangement: Verse Chorus\n section Verse { play A { :| C } }");
+    }
+
+    #[test]
+    fn fail_duplicate_section() {
+        sequence_test_fail("section Verse {} section Verse {}");
+    }
+
+    #[test]
+    fn fail_undeclared_voice_in_section() {
+        sequence_test_fail("section Verse { play A { :| C } }");
+    }
+
+    #[test]
+    fn repeat_multiple_bars() {
+        voice_test(
+            "voice A {} play A { :| C |: D | E :| F }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(65),
+                    length: 1,
+                    position: 5,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat_from_start() {
+        voice_test(
+            "voice A {} play A { :| C | D :| E }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat_bars_many_times() {
+        voice_test(
+            "voice A {} play A { :|: C :|x3 D }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn consecutive_repeats() {
+        voice_test(
+            "voice A {} play A { :|: C :|: D :| }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat_across_staves() {
+        voice_test(
+            "voice A {} play A { :|: C :| D ; :|: E :| G }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat_within_part() {
+        voice_test(
+            "part P { :|: C D :| } voice A {} play A { :| *P | E }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 2,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn fail_when_repeats_do_not_match() {
+        sequence_test_fail("voice A {} play A { :|: C :| D ; :| E :| G }");
+        sequence_test_fail("voice A {} play A { :|: C :| D ; :|: E | G :| }");
+        sequence_test_fail("voice A {} play A { :|: C :|x3 D ; :|: E :| G }");
+    }
+
+    #[test]
+    fn fail_when_repeat_is_empty() {
+        sequence_test_fail("voice A {} play A { :| C :|: :| }");
+    }
+
+    #[test]
+    fn play_voltas() {
+        voice_test(
+            "voice A {} play A { :|: C [1 D :| [2 E | F }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(65),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn play_three_voltas() {
+        voice_test(
+            "voice A {} play A { :| C [1 D :| [2 E :| [3 F }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(65),
+                    length: 1,
+                    position: 5,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn play_multiple_bar_voltas() {
+        voice_test(
+            "voice A {} play A { :|: C [1 D | E :| [2 F | G }",
             vec![
                 Note {
                     midi: midi(60),
-                    length: 4,
+                    length: 1,
                     position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(65),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
                 Note {
                     midi: midi(67),
-                    length: 2,
+                    length: 1,
+                    position: 5,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn repeat_after_voltas() {
+        voice_test(
+            "voice A {} play A { :|: C [1 D :| [2 E |: F :| }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(65),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(65),
+                    length: 1,
+                    position: 5,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn fail_when_voltas_do_not_match() {
+        sequence_test_fail("voice A {} play A { :|: C [1 D :| [2 E ; :|: C D :| E }");
+    }
+
+    #[test]
+    fn play_da_capo() {
+        voice_test(
+            "voice A {} play A { :| C | D D.C. }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn play_da_capo_al_fine() {
+        voice_test(
+            "voice A {} play A { :| C fine | D D.C. al fine }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn play_dal_segno_al_coda() {
+        voice_test(
+            "voice A {} play A { :| C | segno D | E coda | F D.S. al coda | coda G }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(65),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 5,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(67),
+                    length: 1,
                     position: 6,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn skip_repeats_after_da_capo() {
+        voice_test(
+            "voice A {} play A { :|: C :| D D.C. }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn play_last_volta_after_da_capo() {
+        voice_test(
+            "voice A {} play A { :|: C [1 D :| [2 E D.C. }",
+            vec![
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(62),
+                    length: 1,
+                    position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 2,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 3,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(60),
+                    length: 1,
+                    position: 4,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
+                },
+                Note {
+                    midi: midi(64),
+                    length: 1,
+                    position: 5,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
             ],
         );
     }
 
     #[test]
-    fn voice_with_dots() {
-        voice_test(
-            "voice A { } play A { :| A..B C... .8 }",
+    fn fail_on_unresolved_jumps() {
+        sequence_test_fail("voice A {} play A { :| C D.S. }");
+        sequence_test_fail("voice A {} play A { :| C | D D.C. al fine }");
+        sequence_test_fail("voice A {} play A { :| C coda | D D.C. al coda }");
+    }
+
+    #[test]
+    fn tempo_changes() {
+        let source = "voice A {} play A {\ntempo: | - - 90 . |\n:| C D E F | G |\n\ntempo: 60\n\n:| C }";
+        let piece = sequence(source);
+        assert_eq!(
+            piece.voices[0].tempo_changes,
+            vec![
+                TempoChange {
+                    position: 2,
+                    tempo: 90,
+                    ramp_start: None,
+                    accelerando: false,
+                },
+                TempoChange {
+                    position: 8,
+                    tempo: 60,
+                    ramp_start: None,
+                    accelerando: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tempo_changes_follow_repeats() {
+        let source = "voice A {} play A {\ntempo: |: 90 - :| 60 - |\n:|: C D :| E F | }";
+        let piece = sequence(source);
+        assert_eq!(
+            piece.voices[0].tempo_changes,
+            vec![
+                TempoChange {
+                    position: 0,
+                    tempo: 90,
+                    ramp_start: None,
+                    accelerando: false,
+                },
+                TempoChange {
+                    position: 2,
+                    tempo: 90,
+                    ramp_start: None,
+                    accelerando: false,
+                },
+                TempoChange {
+                    position: 4,
+                    tempo: 60,
+                    ramp_start: None,
+                    accelerando: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tempo_stave_does_not_play_notes() {
+        voice_test("voice A {} play A { tempo: | 90 }", vec![]);
+    }
+
+    #[test]
+    fn tempo_ramps() {
+        let source = "voice A {} play A {\ntempo: | 120 - rit. - | - - 60 - |\n:| C | C }";
+        let piece = sequence(source);
+        assert_eq!(
+            piece.voices[0].tempo_changes,
+            vec![
+                TempoChange {
+                    position: 0,
+                    tempo: 120,
+                    ramp_start: None,
+                    accelerando: false,
+                },
+                TempoChange {
+                    position: 6,
+                    tempo: 60,
+                    ramp_start: Some(2),
+                    accelerando: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tempo_ramps_keep_their_written_kind() {
+        let source = "voice A {} play A {\ntempo: | 60 accel. | 30 |\n:| C | C }";
+        let piece = sequence(source);
+        assert_eq!(
+            piece.voices[0].tempo_changes[1],
+            TempoChange {
+                position: 2,
+                tempo: 30,
+                ramp_start: Some(1),
+                accelerando: true,
+            }
+        );
+    }
+
+    #[test]
+    fn meter_changes_apply_to_the_piece() {
+        let source = "voice A {} voice B {}\
+                      play A { :| C | C |\n\nbeats: 3\n\n:| C }\
+                      play B { :| C |\n\nbeats: 2\n\n:| C |\n\nbeats: 3\n\n:| C }";
+        let piece = sequence(source);
+        assert_eq!(
+            piece.meter_changes,
             vec![
-                Note {
-                    midi: midi(57),
-                    length: 3,
-                    position: 0,
-                },
-                Note {
-                    midi: midi(59),
-                    length: 1,
-                    position: 3,
-                },
-                Note {
-                    midi: midi(60),
-                    length: 12,
-                    position: 4,
-                },
-            ],
+                MeterChange { bar: 1, beats: 2 },
+                MeterChange { bar: 2, beats: 3 },
+            ]
         );
+        assert_eq!(piece.beats_in_bar(0), 4);
+        assert_eq!(piece.beats_in_bar(1), 2);
+        assert_eq!(piece.beats_in_bar(5), 3);
+        assert_eq!(piece.beats_before_bar(0), 0);
+        assert_eq!(piece.beats_before_bar(2), 6);
+        assert_eq!(piece.beats_before_bar(4), 12);
     }
 
     #[test]
-    fn voice_with_leading_dots() {
-        voice_test(
-            "voice A { } play A { :| ...C E... G... -... }",
+    fn fail_on_conflicting_meter_changes() {
+        sequence_test_fail(
+            "voice A {} voice B {}\
+             play A { :| C |\n\nbeats: 3\n\n:| C }\
+             play B { :| C |\n\nbeats: 2\n\n:| C }",
+        );
+    }
+
+    #[test]
+    fn meter_changes_follow_repeats() {
+        let source = "voice A {} play A {\nbeats: 3\n:|: C :| D }";
+        let piece = sequence(source);
+        assert_eq!(
+            piece.meter_changes,
             vec![
-                Note {
-                    midi: midi(60),
-                    length: 1,
-                    position: 3,
-                },
-                Note {
-                    midi: midi(64),
-                    length: 4,
-                    position: 4,
-                },
-                Note {
-                    midi: midi(67),
-                    length: 4,
-                    position: 8,
-                },
-            ],
+                MeterChange { bar: 0, beats: 3 },
+                MeterChange { bar: 1, beats: 3 },
+            ]
         );
     }
 
     #[test]
-    fn dots_do_not_carry_across_staves() {
+    fn velocity_stave() {
         voice_test(
-            "voice A { } play A { :| CEGc | ; :| ...g }",
+            "voice A {} play A {\nvel: | - 90 . 40 |\n:| C D E F | G }",
             vec![
                 Note {
                     midi: midi(60),
                     length: 1,
                     position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
                 Note {
-                    midi: midi(64),
+                    midi: midi(62),
                     length: 1,
                     position: 1,
+                    velocity: Some(90),
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
                 Note {
-                    midi: midi(67),
+                    midi: midi(64),
                     length: 1,
                     position: 2,
+                    velocity: Some(90),
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
                 Note {
-                    midi: midi(72),
+                    midi: midi(65),
                     length: 1,
                     position: 3,
+                    velocity: Some(40),
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
                 Note {
-                    midi: midi(79),
-                    length: 1,
-                    position: 3,
+                    midi: midi(67),
+                    length: 4,
+                    position: 4,
+                    velocity: Some(40),
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
             ],
         );
     }
 
     #[test]
-    fn notes_can_be_tied_across_bars() {
+    fn velocity_lasts_until_the_end_of_the_play_block() {
         voice_test(
-            "voice A {} play A { :| CEG. | ..EC }",
+            "voice A {} play A {\nvel: | 90\n:| C } play A { :| C }",
             vec![
                 Note {
                     midi: midi(60),
                     length: 1,
                     position: 0,
-                },
-                Note {
-                    midi: midi(64),
-                    length: 1,
-                    position: 1,
-                },
-                Note {
-                    midi: midi(67),
-                    length: 4,
-                    position: 2,
-                },
-                Note {
-                    midi: midi(64),
-                    length: 1,
-                    position: 6,
+                    velocity: Some(90),
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
                 Note {
                     midi: midi(60),
                     length: 1,
-                    position: 7,
+                    position: 0,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
             ],
         );
     }
 
     #[test]
-    fn repeat_bars() {
+    fn velocity_lasts_until_the_end_of_the_part() {
         voice_test(
-            "voice A {} play A { :| A C | % | }",
+            "voice A {} part R {\nvel: | 30\n:| C } play A { :| *R | D }",
             vec![
                 Note {
-                    midi: midi(57),
+                    midi: midi(60),
                     length: 1,
                     position: 0,
+                    velocity: Some(30),
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
                 Note {
-                    midi: midi(60),
+                    midi: midi(62),
                     length: 1,
                     position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
-                Note {
-                    midi: midi(57),
-                    length: 1,
-                    position: 2,
+            ],
+        );
+    }
+
+    #[test]
+    fn dynamics() {
+        let source = "voice A {} play A {\ndyn: | p < . . | f - > . | p |\n:| C | C | C }";
+        let piece = sequence(source);
+        let voice = &piece.voices[0];
+
+        assert_eq!(
+            voice.dynamic_changes,
+            vec![
+                DynamicChange {
+                    position: 0,
+                    velocity: 49,
+                    ramp_start: None,
                 },
-                Note {
-                    midi: midi(60),
-                    length: 1,
-                    position: 3,
+                DynamicChange {
+                    position: 4,
+                    velocity: 96,
+                    ramp_start: Some(1),
                 },
-            ],
+                DynamicChange {
+                    position: 8,
+                    velocity: 49,
+                    ramp_start: Some(6),
+                },
+            ]
         );
+        assert_eq!(voice.notes[0].velocity, None);
+
+        assert_eq!(voice.dynamic_at(0), Some(49));
+        assert_eq!(voice.dynamic_at(1), Some(49));
+        assert_eq!(voice.dynamic_at(2), Some(65));
+        assert_eq!(voice.dynamic_at(5), Some(96));
+        assert_eq!(voice.dynamic_at(7), Some(73));
+        assert_eq!(voice.dynamic_at(20), Some(49));
     }
 
     #[test]
-    fn repeat_bars_twice() {
+    fn hairpin_without_a_starting_dynamic() {
+        let source = "voice A {} play A {\ndyn: | < . . . | ff |\n:| C | C }";
+        let piece = sequence(source);
+        let voice = &piece.voices[0];
+
+        assert_eq!(voice.dynamic_at(0), Some(DEFAULT_DYNAMIC));
+        assert_eq!(voice.dynamic_at(2), Some(96));
+        assert_eq!(voice.dynamic_at(4), Some(112));
+    }
+
+    #[test]
+    fn articulations_in_parts() {
         voice_test(
-            "voice A {} play A { :| A C | % | % | }",
+            "voice A {} part P { :| C* D } play A { :| *P-12 }",
             vec![
                 Note {
-                    midi: midi(57),
+                    midi: midi(48),
                     length: 1,
                     position: 0,
+                    velocity: None,
+                    articulation: Some(Articulation::Staccato),
+                    strum: None,
+                    ornament: None,
                 },
                 Note {
-                    midi: midi(60),
+                    midi: midi(50),
                     length: 1,
                     position: 1,
+                    velocity: None,
+                    articulation: None,
+                    strum: None,
+                    ornament: None,
                 },
-                Note {
-                    midi: midi(57),
-                    length: 1,
-                    position: 2,
+            ],
+        );
+    }
+
+    #[test]
+    fn tuplets() {
+        let note = |midi_note: i8, length: u32, position: u32| Note {
+            midi: midi(midi_note),
+            length,
+            position,
+            velocity: None,
+            articulation: None,
+            strum: None,
+            ornament: None,
+        };
+
+        voice_test(
+            "voice A {} play A { :| C (3 D E F) G A | (3 C D2)3 E | }",
+            vec![
+                note(60, 3, 0),
+                note(62, 1, 3),
+                note(64, 1, 4),
+                note(65, 1, 5),
+                note(67, 3, 6),
+                note(57, 3, 9),
+                note(60, 3, 12),
+                note(62, 6, 15),
+                note(64, 3, 21),
+            ],
+        );
+    }
+
+    #[test]
+    fn chords() {
+        let note = |midi_note: i8, length: u32, position: u32| Note {
+            midi: midi(midi_note),
+            length,
+            position,
+            velocity: None,
+            articulation: None,
+            strum: None,
+            ornament: None,
+        };
+
+        voice_test(
+            "voice A {} play A {\n:| C . [EG] . |\n:| [cd] - - - |\n}",
+            vec![
+                note(60, 2, 0),
+                note(72, 1, 0),
+                note(74, 1, 0),
+                note(64, 2, 2),
+                note(67, 2, 2),
+            ],
+        );
+    }
+
+    #[test]
+    fn chord_symbols() {
+        let positions = |source: &str| -> Vec<(i8, u32, u32)> {
+            let piece = sequence(source);
+            piece.voices[0]
+                .notes
+                .iter()
+                .map(|note| (note.midi.midi(), note.position, note.length))
+                .collect()
+        };
+
+        assert_eq!(
+            positions("voice A {} play A { chords: | C . G7/B | }"),
+            vec![
+                (60, 0, 2),
+                (64, 0, 2),
+                (67, 0, 2),
+                (59, 2, 1),
+                (67, 2, 1),
+                (71, 2, 1),
+                (74, 2, 1),
+                (77, 2, 1),
+            ]
+        );
+        assert_eq!(
+            positions("voice A { voicing: root, chord_octave: -2, octave: 1 } play A { chords: | C G7/B | }"),
+            vec![(48, 0, 1), (59, 1, 1)]
+        );
+        assert_eq!(
+            positions("voice A { voicing: open } part P { chords: | C | } play A { :| *P+2 | }"),
+            vec![(62, 0, 1), (69, 0, 1), (78, 0, 1)]
+        );
+    }
+
+    #[test]
+    fn arpeggios() {
+        let pitches = |source: &str| -> Vec<(i8, u32, u32)> {
+            let piece = sequence(source);
+            let voice = &piece.voices[0];
+            let scale = voice.divisions_per_bar / 8;
+            voice
+                .notes
+                .iter()
+                .map(|note| (note.midi.midi(), note.position / scale, note.length / scale))
+                .collect()
+        };
+
+        assert_eq!(
+            pitches("voice A { arpeggio: up, arp_rate: 8 } play A { :| [CEG]3 C | }"),
+            vec![
+                (60, 0, 1),
+                (64, 1, 1),
+                (67, 2, 1),
+                (60, 3, 1),
+                (64, 4, 1),
+                (67, 5, 1),
+                (60, 6, 2),
+            ]
+        );
+        assert_eq!(
+            pitches("voice A { arpeggio: down, arp_rate: 8 } play A { :| [CE] D2 . | }"),
+            vec![(64, 0, 1), (60, 1, 1), (62, 2, 6)]
+        );
+        assert_eq!(
+            pitches("voice A { arpeggio: updown, arp_rate: 8 } play A { :| [CEG]2 -2 | }"),
+            vec![(60, 0, 1), (64, 1, 1), (67, 2, 1), (64, 3, 1)]
+        );
+    }
+
+    #[test]
+    fn arpeggios_follow_the_meter() {
+        let source = "voice A { arpeggio: up, arp_rate: 4 } play A { :| [CE] | \n\nbeats: 2\n\n:| [CE] | }";
+        let piece = sequence(source);
+        let voice = &piece.voices[0];
+
+        // Every bar has the same number of divisions, so a bar of 2/4 has divisions twice as long.
+        let notes = voice
+            .notes
+            .iter()
+            .map(|note| (note.position, note.length))
+            .collect::<Vec<_>>();
+        assert_eq!(voice.divisions_per_bar, 4);
+        assert_eq!(notes, vec![(0, 1), (1, 1), (2, 1), (3, 1), (4, 2), (6, 2)]);
+    }
+
+    #[test]
+    fn random_arpeggios_are_seeded() {
+        let pitches = |seed: u64| -> Vec<i8> {
+            let source = format!(
+                "voice A {{ arpeggio: random, arp_seed: {} }} play A {{ chords: | Cmaj7 | }}",
+                seed
+            );
+            let piece = sequence(&source);
+            piece.voices[0]
+                .notes
+                .iter()
+                .map(|note| note.midi.midi())
+                .collect()
+        };
+
+        assert_eq!(pitches(1).len(), 16);
+        assert_eq!(pitches(1), pitches(1));
+        assert_ne!(pitches(1), pitches(2));
+        assert!(pitches(1).iter().all(|midi| [60, 64, 67, 71].contains(midi)));
+    }
+
+    #[test]
+    fn strums() {
+        let note = |midi_note: i8, position: u32, strum: Option<StrumDirection>| Note {
+            midi: midi(midi_note),
+            length: 1,
+            position,
+            velocity: None,
+            articulation: None,
+            strum,
+            ornament: None,
+        };
+
+        voice_test(
+            "voice A { strum: 10, strum_direction: up } play A {\n:| ^[CE] v[CE] [CE] |\n}",
+            vec![
+                note(60, 0, Some(StrumDirection::Up)),
+                note(64, 0, Some(StrumDirection::Up)),
+                note(60, 1, Some(StrumDirection::Down)),
+                note(64, 1, Some(StrumDirection::Down)),
+                note(60, 2, None),
+                note(64, 2, None),
+            ],
+        );
+
+        let piece = sequence("voice A { strum: 10ms, strum_direction: up } play A { :| C }");
+        assert_eq!(piece.voices[0].strum, Some(Strum::Milliseconds(10)));
+        assert_eq!(piece.voices[0].strum_direction, StrumDirection::Up);
+    }
+
+    #[test]
+    fn grace_notes() {
+        let note = |midi_note: i8, length: u32, ornament: Option<Ornament>| Note {
+            midi: midi(midi_note),
+            length,
+            position: 0,
+            velocity: None,
+            articulation: None,
+            strum: None,
+            ornament,
+        };
+
+        voice_test(
+            "voice A { octave: 1 } play A { :| {dB}c~tr . }",
+            vec![
+                note(86, 16, Some(Ornament::GraceNote)),
+                note(71, 16, Some(Ornament::GraceNote)),
+                note(84, 32, Some(Ornament::Trill { upper: 2 })),
+            ],
+        );
+    }
+
+    #[test]
+    fn play_ornaments() {
+        let played = |source: &str| -> Vec<(i8, u32, u32)> {
+            let piece = sequence(source);
+            let voice = &piece.voices[0];
+            assert_eq!(voice.divisions_per_bar, 32);
+
+            super::play_ornaments(voice, &piece)
+                .iter()
+                .map(|note| (note.midi.midi(), note.position, note.length))
+                .collect()
+        };
+
+        assert_eq!(
+            played("voice A {} play A { :| C~tr - - - }"),
+            vec![
+                (62, 0, 1),
+                (60, 1, 1),
+                (62, 2, 1),
+                (60, 3, 1),
+                (62, 4, 1),
+                (60, 5, 1),
+                (62, 6, 1),
+                (60, 7, 1),
+            ]
+        );
+        assert_eq!(
+            played("voice A {} play A { :| C~mor - - - }"),
+            vec![(60, 0, 1), (59, 1, 1), (60, 2, 6)]
+        );
+        assert_eq!(
+            played("voice A {} play A { :| C~turn - - - }"),
+            vec![(62, 0, 1), (60, 1, 1), (59, 2, 1), (60, 3, 5)]
+        );
+        assert_eq!(
+            played("voice A {} play A { :| {dB}C - - - }"),
+            vec![(74, 0, 1), (59, 1, 1), (60, 2, 6)]
+        );
+        assert_eq!(
+            played("voice A {} play A { :| {GF}C~tr C C C C C C C C C C C C C C C }"),
+            (0..16).map(|index| (60, index * 2, 2)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn bends() {
+        let source = "voice A { bend_range: 7 } play A {\nbend: | 1 / . -1 | 0.5 |\n:| C | C }";
+        let piece = sequence(source);
+        let voice = &piece.voices[0];
+
+        assert_eq!(voice.bend_range, 7);
+        assert_eq!(
+            voice.bend_changes,
+            vec![
+                BendChange {
+                    position: 0,
+                    cents: 100,
+                    ramp_start: None,
                 },
-                Note {
-                    midi: midi(60),
-                    length: 1,
+                BendChange {
                     position: 3,
+                    cents: -100,
+                    ramp_start: Some(1),
                 },
-                Note {
-                    midi: midi(57),
-                    length: 1,
+                BendChange {
                     position: 4,
+                    cents: 50,
+                    ramp_start: None,
                 },
-                Note {
-                    midi: midi(60),
-                    length: 1,
-                    position: 5,
+            ]
+        );
+
+        assert_eq!(voice.bend_at(1), 100);
+        assert_eq!(voice.bend_at(2), 0);
+        assert_eq!(voice.bend_at(3), -100);
+        assert_eq!(voice.bend_at(10), 50);
+    }
+
+    #[test]
+    fn glissandos() {
+        let source = "voice A {} part P { :| C/ E } play A { :| *P+2 | {D}C/ c }";
+        let piece = sequence(source);
+        let voice = &piece.voices[0];
+
+        let played = super::play_ornaments(voice, &piece)
+            .iter()
+            .map(|note| (note.midi.midi(), note.ornament))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            played,
+            vec![
+                (62, Some(Ornament::Glissando { semitones: 4 })),
+                (66, None),
+                (62, None),
+                (60, Some(Ornament::Glissando { semitones: 12 })),
+                (72, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn controller_changes() {
+        let source = "voice A {} play A {\ncc10: | 0 / . 127 |\ncc1: | 64 |\n:| C | C }";
+        let piece = sequence(source);
+        let voice = &piece.voices[0];
+
+        assert_eq!(
+            voice.controller_changes,
+            vec![
+                ControllerChange {
+                    position: 0,
+                    controller: 1,
+                    value: 64,
+                    ramp_start: None,
                 },
-            ],
+                ControllerChange {
+                    position: 0,
+                    controller: 10,
+                    value: 0,
+                    ramp_start: None,
+                },
+                ControllerChange {
+                    position: 3,
+                    controller: 10,
+                    value: 127,
+                    ramp_start: Some(1),
+                },
+            ]
         );
     }
 
     #[test]
-    fn fail_first_bar_repeat() {
-        sequence_test_fail("voice A {} play A { :| % | }");
+    fn pedal_marks_change_the_sustain_pedal() {
+        let source = "voice A {} play A {\nped: | v . ^ v |\n:| C D E F }";
+        let piece = sequence(source);
+        let voice = &piece.voices[0];
+
+        assert_eq!(
+            voice
+                .controller_changes
+                .iter()
+                .map(|change| (change.position, change.controller, change.value))
+                .collect::<Vec<_>>(),
+            vec![(0, 64, 127), (2, 64, 0), (3, 64, 127)]
+        );
+    }
+
+    #[test]
+    fn mix_controllers() {
+        let source = "voice A { pan: -32, reverb: 40, expression: 100, portamento: 10 }";
+        let piece = sequence(source);
+
+        assert_eq!(
+            piece.voices[0].mix_controllers(),
+            vec![(10, 32), (91, 40), (11, 100), (5, 10), (65, 127)]
+        );
+    }
+
+    #[test]
+    fn pressure_changes() {
+        let source = "voice A { octave: -1 } play A {\n\
+                      pressure: | 0 / . 100 |\n\
+                      pressure_E: | 64 |\n\
+                      :| [CEG] }";
+        let piece = sequence(source);
+        let voice = &piece.voices[0];
+
+        assert_eq!(
+            voice.pressure_changes,
+            vec![
+                PressureChange {
+                    position: 0,
+                    note: None,
+                    pressure: 0,
+                    ramp_start: None,
+                },
+                PressureChange {
+                    position: 0,
+                    note: Some(midi(52)),
+                    pressure: 64,
+                    ramp_start: None,
+                },
+                PressureChange {
+                    position: 3,
+                    note: None,
+                    pressure: 100,
+                    ramp_start: Some(1),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pressure_notes_follow_the_key() {
+        let source = "key: F major\nvoice A {} play A {\npressure_B: | 64 |\n:| B }";
+        let piece = sequence(source);
+        let voice = &piece.voices[0];
+
+        assert_eq!(voice.notes[0].midi, midi(58));
+        assert_eq!(voice.pressure_changes[0].note, Some(midi(58)));
     }
 }
@@ -12,9 +12,10 @@ pub fn stave(prefix: &str, notes: Vec<Vec<NoteNode>>) -> StaveNode {
                 BarTypeNode::Bar(BarNode {
                     notes: bar,
                     note_locs: Vec::new(),
+                    tuplets: Vec::new(),
                 })
             }).collect(),
-        bar_locs: Vec::new(),
+        ..Default::default()
     }
 }
 
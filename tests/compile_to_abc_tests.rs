@@ -31,3 +31,22 @@ test_abc!(simple_melody);
 test_abc!(complex_melody);
 test_abc!(longer_melody);
 test_abc!(fifths);
+test_abc!(key_signatures);
+test_abc!(parts);
+test_abc!(sections);
+test_abc!(repeats);
+test_abc!(voltas);
+test_abc!(navigation);
+test_abc!(tempo_changes);
+test_abc!(tempo_ramps);
+test_abc!(meter_changes);
+test_abc!(compound_meter);
+test_abc!(dynamics);
+test_abc!(articulations);
+test_abc!(tuplets);
+test_abc!(chords);
+test_abc!(chord_symbols);
+test_abc!(arpeggios);
+test_abc!(ornaments);
+test_abc!(pedal);
+test_abc!(voice_mix);